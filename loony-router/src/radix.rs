@@ -14,6 +14,12 @@ pub struct RadixRouter {
     pub root: RadixNode,
 }
 
+impl Default for RadixRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RadixRouter {
     pub fn new() -> Self {
         Self { root: RadixNode::default() }
@@ -34,9 +40,9 @@ impl RadixRouter {
         let segment = segments[0];
         let remaining = &segments[1..];
 
-        if segment.starts_with(':') {
+        if let Some(segment) = segment.strip_prefix(':') {
             // Parameter segment
-            let param_name = segment[1..].to_string();
+            let param_name = segment.to_string();
             if let Some((existing_name, child_node)) = &mut node.param_child {
                 if *existing_name != param_name {
                     panic!("Conflicting parameter names");
@@ -72,17 +78,16 @@ impl RadixRouter {
     ) -> Option<usize> {
 
         if segments.is_empty() {
-            return node.service_index.clone();
+            return node.service_index;
         }
 
         let segment = segments[0];
         let remaining = &segments[1..];
 
         // Try static match first
-        if let Some(child_node) = node.static_children.get(segment) {
-            if let Some(service_index) = Self::find_in_node(child_node, remaining, params) {
-                return Some(service_index);
-            }
+        if let Some(child_node) = node.static_children.get(segment)
+            && let Some(service_index) = Self::find_in_node(child_node, remaining, params) {
+            return Some(service_index);
         }
 
         // Try parameter match
@@ -94,7 +99,8 @@ impl RadixRouter {
             params.remove(param_name);
         }
 
-        node.service_index.clone()
+        // Segments are left over, so this node's own route does not match
+        None
     }
 
 }
\ No newline at end of file
@@ -12,13 +12,40 @@ use crate::service::{AppServiceFactory};
 pub struct App {
     pub extensions: Extensions,
     pub services: Vec<Box<dyn AppServiceFactory>>,
+    pub shutdown_hooks: Vec<Box<dyn FnOnce()>>,
+    pub problem_json: bool,
+    pub panic_hook: Option<PanicHook>,
+}
+
+/// The hook registered with `App::on_panic`.
+pub type PanicHook = Box<dyn Fn(&HandlerPanic)>;
+
+/// A handler panic, as passed to the hook registered with `App::on_panic`.
+#[derive(Debug, Clone)]
+pub struct HandlerPanic {
+    pub method: String,
+    /// The request path, without the query string.
+    pub path: String,
+    /// The route pattern that matched, such as `/users/:id`.
+    pub route: String,
+    /// The panic payload, if it was a string.
+    pub message: String,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl App {
     pub fn new() -> Self {
       App { 
         extensions: Extensions::new(),
-        services: Vec::new()
+        services: Vec::new(),
+        shutdown_hooks: Vec::new(),
+        problem_json: false,
+        panic_hook: None,
       } 
     }
 
@@ -38,11 +65,38 @@ impl App {
         self
     }
 
-    pub fn routes<'a, T>(mut self, cnfg: T) -> Self where T: Fn() -> Router {
+    pub fn routes<T>(mut self, cnfg: T) -> Self where T: Fn() -> Router {
         let router = cnfg();
         self.services.extend(router.services);
         self
     }
+
+    /// Renders every error response, from routing, extractors, request
+    /// parsing and handlers alike, as an RFC 7807 `application/problem+json`
+    /// body instead of plain text.
+    pub fn problem_json(mut self) -> Self {
+        self.problem_json = true;
+        self
+    }
+
+    /// Registers a hook called whenever a handler panics, such as to report
+    /// it to an error tracker. The panic is caught either way: it is logged,
+    /// the request is answered with a 500 and the worker keeps serving.
+    ///
+    /// Every worker builds its own `App`, so each has its own hook.
+    pub fn on_panic<F>(mut self, hook: F) -> Self where F: Fn(&HandlerPanic) + 'static {
+        self.panic_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers a hook to run when the server shuts down.
+    ///
+    /// Every worker builds its own `App`, so the hook runs once per worker,
+    /// after that worker has finished serving its connections.
+    pub fn on_shutdown<F>(mut self, hook: F) -> Self where F: FnOnce() + 'static {
+        self.shutdown_hooks.push(Box::new(hook));
+        self
+    }
 }
 
 impl IntoServiceFactory<AppFactory> for App {
@@ -50,6 +104,9 @@ impl IntoServiceFactory<AppFactory> for App {
         AppFactory {
             services: Rc::new(RefCell::new(self.services)),
             extensions: RefCell::new(Some(self.extensions)),
+            shutdown_hooks: RefCell::new(self.shutdown_hooks),
+            problem_json: self.problem_json,
+            panic_hook: RefCell::new(self.panic_hook),
         }
     }
 }
\ No newline at end of file
@@ -2,10 +2,10 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use futures::future::ready;
 use futures::{future::Ready};
-use std::collections::HashMap;
+use crate::app::PanicHook;
+use crate::error::Error;
 use crate::route::RouteServices;
 use crate::extensions::Extensions;
-use crate::resource::FinalRouteService;
 use crate::router::AllRouteServices;
 use crate::service::{AppServiceFactory};
 use loony_service::{ServiceFactory, Service};
@@ -13,6 +13,9 @@ use loony_service::{ServiceFactory, Service};
 pub struct AppFactory {
     pub services: Rc<RefCell<Vec<Box<dyn AppServiceFactory>>>>,
     pub extensions: RefCell<Option<Extensions>>,
+    pub shutdown_hooks: RefCell<Vec<Box<dyn FnOnce()>>>,
+    pub problem_json: bool,
+    pub panic_hook: RefCell<Option<PanicHook>>,
 }
 
 impl ServiceFactory for AppFactory {
@@ -20,7 +23,7 @@ impl ServiceFactory for AppFactory {
 
     type Response = ();
 
-    type Error = ();
+    type Error = Error;
 
     type Config = ();
 
@@ -37,11 +40,13 @@ impl ServiceFactory for AppFactory {
         .for_each(|mut srv| srv.register(&mut route_services));
         let mut radix_router = AllRouteServices::new();
         let route_services = route_services.into_services();
-        println!("{}", route_services.len());
         // let mut routes = AHashMap::new();
         route_services.iter().for_each(|f| {
-            let route = f.borrow().route_name.clone();
-            radix_router.add_route(&route, Rc::clone(&f));
+            let (route, methods) = {
+                let service = f.borrow();
+                (service.route_name.clone(), service.methods.clone())
+            };
+            radix_router.add_route(&route, &methods, Rc::clone(f));
             // let segments: Vec<&str> = route.split('/').filter(|s| !s.is_empty())
             // .filter(|s| !s.contains(":")).collect();
             // let uri = segments.join("");
@@ -51,10 +56,13 @@ impl ServiceFactory for AppFactory {
             .extensions
             .borrow_mut()
             .take()
-            .unwrap_or_else(Extensions::new);
+            .unwrap_or_default();
         ready(Ok(AppHttpService {
             route: radix_router,
             extensions,
+            shutdown_hooks: std::mem::take(&mut *self.shutdown_hooks.borrow_mut()),
+            problem_json: self.problem_json,
+            panic_hook: self.panic_hook.borrow_mut().take(),
         }))
     }
 }
@@ -63,6 +71,9 @@ pub struct AppHttpService {
     // pub(crate) routes: AHashMap<String, Rc<RefCell<FinalRouteService>>>,
     pub(crate) extensions: Extensions,
     pub(crate) route: AllRouteServices,
+    pub(crate) shutdown_hooks: Vec<Box<dyn FnOnce()>>,
+    pub(crate) problem_json: bool,
+    pub(crate) panic_hook: Option<PanicHook>,
 }
 
 impl Service for AppHttpService {
@@ -70,9 +81,9 @@ impl Service for AppHttpService {
 
     type Response = ();
 
-    type Error = ();
+    type Error = Error;
 
-    type Future = Ready<Result<(), ()>>;
+    type Future = Ready<Result<(), Error>>;
 
     fn call(&mut self, _: Self::Request) -> Self::Future {
         ready(Ok(()))
@@ -1,34 +1,101 @@
 use std::{
-    io::{self, Read, Write, ErrorKind},
-    net::{TcpStream, Shutdown, SocketAddr},
+    future::Future,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    panic::AssertUnwindSafe,
     time::Duration,
 };
 
-/// Represents a single TCP connection with buffered I/O.
-pub struct Connection {
-    stream: TcpStream,
+use futures_util::FutureExt;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::{timeout, timeout_at, Instant},
+};
+
+use crate::{error::{ConnectionError, ParseError, ServerError, ServerResult}, request::HttpRequest, response::{BodyStream, HttpResponse, StatusCode}, server::{ServerConfig, panic_message}};
+
+/// A byte stream a `Connection` can serve requests over.
+pub trait IoStream: AsyncRead + AsyncWrite + Unpin {
+    /// Applies transport specific socket options before the first read.
+    fn configure(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl IoStream for TcpStream {
+    fn configure(&self) -> io::Result<()> {
+        self.set_nodelay(true) // Disable Nagle’s algorithm for latency-sensitive use
+    }
+}
+
+#[cfg(unix)]
+impl IoStream for tokio::net::UnixStream {}
+
+/// Represents a single client connection with buffered I/O.
+///
+/// Bytes read past the end of a request are kept in `pending`, so requests
+/// pipelined by the client are served in order without touching the socket.
+pub struct Connection<S = TcpStream> {
+    stream: S,
     buffer: Vec<u8>,
+    pending: Vec<u8>,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    keep_alive: Duration,
+    header_read_timeout: Duration,
+    max_request_size: usize,
+    max_head_size: usize,
+    opened: Instant,
+    served: usize,
 }
 
-impl Connection {
-    /// Create a new `Connection` from a `TcpStream`, configuring sensible defaults.
-    pub fn new(stream: TcpStream) -> io::Result<Self> {
-        // Configure timeouts and TCP settings
-        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
-        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
-        stream.set_nodelay(true)?; // Disable Nagle’s algorithm for latency-sensitive use
-        
+impl<S: IoStream> Connection<S> {
+    /// Create a new `Connection` from a stream, taking its timeouts and
+    /// limits from `config`.
+    pub fn new(stream: S, config: &ServerConfig) -> io::Result<Self> {
+        stream.configure()?;
+
         Ok(Self {
             stream,
             buffer: vec![0u8; 8192], // 8KB internal buffer
+            pending: Vec::new(),
+            read_timeout: config.read_timeout,
+            write_timeout: config.write_timeout,
+            keep_alive: config.keep_alive,
+            header_read_timeout: config.header_read_timeout,
+            max_request_size: config.max_request_size,
+            max_head_size: config.max_head_size,
+            opened: Instant::now(),
+            served: 0,
         })
     }
 
+    /// Reads once from the stream, appending whatever arrives to `pending`.
+    ///
+    /// No timeout is applied here; callers pick the one that fits the phase
+    /// of the request they are in.
+    async fn fill(&mut self) -> io::Result<usize> {
+        let n = self.stream.read(&mut self.buffer).await?;
+        self.pending.extend_from_slice(&self.buffer[..n]);
+        Ok(n)
+    }
+
+    /// Like `fill`, but treats EOF as an error since more data was expected.
+    async fn fill_more(&mut self) -> io::Result<()> {
+        match self.fill().await? {
+            0 => Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed mid-request")),
+            _ => Ok(()),
+        }
+    }
+
     /// Reads exactly `buf.len()` bytes or until EOF.
-    pub fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut total_read = 0;
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut total_read = self.pending.len().min(buf.len());
+        buf[..total_read].copy_from_slice(&self.pending[..total_read]);
+        self.pending.drain(..total_read);
         while total_read < buf.len() {
-            match self.stream.read(&mut buf[total_read..]) {
+            match with_timeout(self.read_timeout, self.stream.read(&mut buf[total_read..])).await {
                 Ok(0) => {
                     // If we read 0 and nothing read so far, treat as EOF
                     if total_read == 0 {
@@ -46,133 +113,372 @@ impl Connection {
     }
 
     /// Reads data until a specified delimiter byte is encountered.
-    pub fn read_until(&mut self, delimiter: u8) -> io::Result<Vec<u8>> {
-        let mut result = Vec::new();
+    pub async fn read_until(&mut self, delimiter: u8) -> io::Result<Vec<u8>> {
+        let mut searched = 0;
         loop {
-            let bytes_read = self.stream.read(&mut self.buffer)?;
-            if bytes_read == 0 {
-                // EOF reached
-                break;
+            if let Some(pos) = self.pending[searched..].iter().position(|&b| b == delimiter) {
+                let rest = self.pending.split_off(searched + pos + 1);
+                return Ok(std::mem::replace(&mut self.pending, rest));
             }
-
-            for &b in &self.buffer[..bytes_read] {
-                result.push(b);
-                if b == delimiter {
-                    return Ok(result);
-                }
+            searched = self.pending.len();
+            if with_timeout(self.read_timeout, self.fill()).await? == 0 {
+                // EOF reached
+                return Ok(std::mem::take(&mut self.pending));
             }
         }
-        Ok(result)
     }
 
     /// Reads all available data until EOF.
-    pub fn read_all(&mut self) -> io::Result<Vec<u8>> {
-        let mut result = Vec::new();
+    pub async fn read_all(&mut self) -> io::Result<Vec<u8>> {
         loop {
-            match self.stream.read(&mut self.buffer) {
+            match with_timeout(self.read_timeout, self.fill()).await {
                 Ok(0) => break, // EOF
-                Ok(n) => result.extend_from_slice(&self.buffer[..n]),
-                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                    // For non-blocking sockets: return what’s read so far
-                    break;
-                }
+                Ok(_) => continue,
                 Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
                 Err(e) => return Err(e),
             }
         }
-        Ok(result)
+        Ok(std::mem::take(&mut self.pending))
     }
 
     /// Writes all bytes from `data` to the stream.
-    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
-        let mut total_written = 0;
-        while total_written < data.len() {
-            match self.stream.write(&data[total_written..]) {
-                Ok(0) => {
-                    return Err(io::Error::new(
-                        ErrorKind::WriteZero,
-                        "failed to write the entire buffer",
-                    ));
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let write = async {
+            self.stream.write_all(data).await?;
+            self.stream.flush().await
+        };
+        with_timeout(self.write_timeout, write).await
+    }
+
+    /// Like `write`, for the server's own writes, which fail as a
+    /// `ConnectionError`.
+    async fn send(&mut self, data: &[u8]) -> ServerResult<()> {
+        self.write(data).await.map_err(|e| ConnectionError::write(e).into())
+    }
+
+    /// Waits for the next request to start arriving.
+    ///
+    /// Returns `false` once the client has closed the connection, or has sat
+    /// idle between requests for longer than the keep-alive timeout.
+    /// Cancelling this future never loses data.
+    pub async fn wait_for_request(&mut self) -> ServerResult<bool> {
+        // Pipelined requests are already sitting in `pending`, so there is
+        // nothing to wait for.
+        if !self.pending.is_empty() {
+            return Ok(true);
+        }
+        let idle = if self.served == 0 { self.header_read_timeout } else { self.keep_alive };
+        match timeout(idle, self.fill()).await {
+            Ok(Ok(0)) | Err(_) => Ok(false),
+            Ok(Ok(_)) => Ok(true),
+            Ok(Err(e)) => Err(ConnectionError::read(e).into()),
+        }
+    }
+
+    /// Reads the next complete request.
+    ///
+    /// The body is framed by `Content-Length` or `Transfer-Encoding: chunked`;
+    /// a chunked body is returned decoded, with its trailers appended to the
+    /// headers. Returns `None` if the connection closes before a new request
+    /// starts, see `wait_for_request`. Headers must arrive within the
+    /// header-read timeout and the whole request must fit in the configured
+    /// maximum request size.
+    pub async fn read_http_response(&mut self) -> ServerResult<Option<Vec<u8>>> {
+        if !self.wait_for_request().await? {
+            return Ok(None);
+        }
+
+        // First, read headers. A new connection has to deliver its first
+        // request head within the timeout counted from when it was accepted.
+        let deadline = if self.served == 0 {
+            self.opened + self.header_read_timeout
+        } else {
+            Instant::now() + self.header_read_timeout
+        };
+        // Each search picks up where the last one ended, less the three
+        // bytes a terminator split across reads could start with.
+        let mut searched = 0;
+        let headers_end = loop {
+            let end = find_headers_end(&self.pending[searched..]).map(|end| searched + end);
+            if end.unwrap_or(self.pending.len()) > self.max_head_size {
+                return Err(ParseError::HeadTooLarge { limit: self.max_head_size }.into());
+            }
+            if let Some(end) = end {
+                break end;
+            }
+            searched = self.pending.len().saturating_sub(3);
+            match timeout_at(deadline, self.fill_more()).await {
+                Ok(res) => res.map_err(ConnectionError::read)?,
+                Err(_) => return Err(ServerError::timeout("reading request headers")),
+            }
+        };
+
+        // Then the body. Anything a proxy in front of us could frame
+        // differently is refused: both framings at once, repeated framing
+        // headers, or a Content-Length that is not plain digits. The head
+        // is parsed just as the request will be, so both see the same
+        // headers.
+        let mut head = HttpRequest::new();
+        head.parse_head(&self.pending[..headers_end])?;
+        let lengths: Vec<_> = head.headers_named("content-length").collect();
+        let content_length = match lengths[..] {
+            [] => None,
+            [length] => Some(length),
+            _ => return Err(ParseError::malformed_headers("repeated Content-Length").into()),
+        };
+        let encodings: Vec<_> = head.headers_named("transfer-encoding").collect();
+        let chunked = match encodings[..] {
+            [] => false,
+            [_] if content_length.is_some() => return Err(ParseError::AmbiguousLength.into()),
+            [encoding] => {
+                // Only chunked is decoded; any other coding would reach the
+                // handler still encoded.
+                if !encoding.eq_ignore_ascii_case("chunked") {
+                    return Err(ParseError::UnsupportedTransferEncoding { encoding: encoding.to_string() }.into());
                 }
-                Ok(n) => total_written += n,
-                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(e) => return Err(e),
+                true
             }
+            _ => return Err(ParseError::malformed_headers("repeated Transfer-Encoding").into()),
+        };
+        let content_length = match content_length {
+            Some(value) => parse_content_length(value)?,
+            None => 0,
+        };
+        // A body that cannot fit is refused before the client is told to
+        // send it.
+        let request_len = headers_end.saturating_add(content_length);
+        if request_len > self.max_request_size {
+            return Err(ParseError::buffer_overflow(request_len, self.max_request_size).into());
         }
-        self.stream.flush()?;
-        Ok(())
+        let expects_continue = head.version == Some(1) && head.header("expect")
+            .map(|v| v.eq_ignore_ascii_case("100-continue"))
+            .unwrap_or(false);
+        if expects_continue && (chunked || content_length > 0) && self.pending.len() == headers_end {
+            self.send(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+        }
+
+        let (request, request_len) = if chunked {
+            self.read_chunked(headers_end).await?
+        } else {
+            match self.fill_to(request_len).await {
+                Err(ServerError::ConnectionError { source: ConnectionError::ConnectionClosed }) => {
+                    let actual = self.pending.len() - headers_end;
+                    return Err(ParseError::ContentLengthMismatch { expected: content_length, actual }.into());
+                }
+                res => res?,
+            }
+            (self.pending[..request_len].to_vec(), request_len)
+        };
+
+        // Anything past this request belongs to the next one
+        self.pending.drain(..request_len);
+        self.served += 1;
+        Ok(Some(request))
     }
 
-    pub fn read_http_response(&mut self) -> io::Result<Vec<u8>> {
-        let mut result = Vec::new();
-        let mut buffer = [0u8; 1024];
-        let mut content_length = None;
-        
-        // First, read headers
+    /// Decodes a chunked body starting at `headers_end`.
+    ///
+    /// Returns the request with the decoded body, and how many bytes of
+    /// `pending` it took up. Chunk extensions are ignored.
+    async fn read_chunked(&mut self, headers_end: usize) -> ServerResult<(Vec<u8>, usize)> {
+        let mut body = Vec::new();
+        let mut pos = headers_end;
         loop {
-            let bytes_read = self.stream.read(&mut buffer)?;
-            if bytes_read == 0 {
+            let line_end = self.fill_line(pos).await?;
+            let size = parse_chunk_size(&self.pending[pos..line_end - 2])?;
+            pos = line_end;
+            if size == 0 {
                 break;
             }
-            
-            result.extend_from_slice(&buffer[..bytes_read]);
-            
-            // Check if we've received all headers
-            if let Some(headers_end) = find_headers_end(&result) {
-                // Parse Content-Length header if present
-                content_length = parse_content_length(&result[..headers_end]);
-                
-                // If no content length or zero, we're done
-                if content_length.unwrap_or(0) == 0 {
-                    break;
-                }
-                
-                // Read the remaining body
-                let body_bytes_needed = content_length.unwrap() - (result.len() - headers_end);
-                if body_bytes_needed > 0 {
-                    let mut body = vec![0u8; body_bytes_needed];
-                    self.read_exact(&mut body)?; // Now safe to use read_exact
-                    result.extend_from_slice(&body);
-                }
+            let data_end = pos.saturating_add(size);
+            self.fill_to(data_end.saturating_add(2)).await?;
+            if &self.pending[data_end..data_end + 2] != b"\r\n" {
+                return Err(ParseError::invalid_chunk("chunk data not followed by CRLF").into());
+            }
+            body.extend_from_slice(&self.pending[pos..data_end]);
+            pos = data_end + 2;
+        }
+
+        // Trailer fields up to the empty line. Ones that would change how
+        // the request is framed or routed are dropped.
+        let mut trailers = Vec::new();
+        loop {
+            let line_end = self.fill_line(pos).await?;
+            let line = &self.pending[pos..line_end];
+            pos = line_end;
+            if line == b"\r\n" {
                 break;
             }
+            let name = line.split(|&b| b == b':').next().unwrap_or_default();
+            let forbidden = [&b"content-length"[..], b"transfer-encoding", b"host"]
+                .iter()
+                .any(|f| name.trim_ascii().eq_ignore_ascii_case(f));
+            if !forbidden {
+                trailers.extend_from_slice(line);
+            }
+        }
+
+        let mut request = Vec::with_capacity(headers_end + trailers.len() + body.len());
+        request.extend_from_slice(&self.pending[..headers_end - 2]);
+        request.extend_from_slice(&trailers);
+        request.extend_from_slice(b"\r\n");
+        request.extend_from_slice(&body);
+        Ok((request, pos))
+    }
+
+    /// Reads until `pending` holds at least `len` bytes, giving the client
+    /// the read timeout for each read.
+    async fn fill_to(&mut self, len: usize) -> ServerResult<()> {
+        if len > self.max_request_size {
+            return Err(ParseError::buffer_overflow(len, self.max_request_size).into());
+        }
+        while self.pending.len() < len {
+            match timeout(self.read_timeout, self.fill_more()).await {
+                Ok(res) => res.map_err(ConnectionError::read)?,
+                Err(_) => return Err(ServerError::timeout("reading request body")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads until `pending` holds a CRLF terminated line starting at
+    /// `from`, returning the index just past it.
+    async fn fill_line(&mut self, from: usize) -> ServerResult<usize> {
+        let mut searched = from;
+        loop {
+            if let Some(pos) = self.pending[searched..].windows(2).position(|w| w == b"\r\n") {
+                return Ok(searched + pos + 2);
+            }
+            searched = self.pending.len().saturating_sub(1).max(from);
+            self.fill_to(self.pending.len() + 1).await?;
+        }
+    }
+
+    /// Serializes `response` and writes it out. This is the only place a
+    /// response is turned into bytes.
+    ///
+    /// The framing headers are always set here: `Content-Length` for a full
+    /// body, chunked encoding for a streamed one if `chunked`, and
+    /// `Connection` according to `keep_alive`. For a `head_request` the
+    /// headers describe the body as usual, but the body itself is left out.
+    /// A 1xx, 204 or 304 response is sent without a body or framing header.
+    pub async fn write_response(
+        &mut self,
+        mut response: HttpResponse,
+        chunked: bool,
+        keep_alive: bool,
+        head_request: bool,
+    ) -> ServerResult<()> {
+        // 1xx, 204 and 304 responses never have a body, so they carry no
+        // framing headers either.
+        let status = response.status_code();
+        let bodiless = status.is_informational()
+            || matches!(status, StatusCode::NoContent | StatusCode::NotModified);
+        let stream = response.stream.take().filter(|_| !bodiless);
+        response.headers.retain(|name, _| {
+            !["content-length", "transfer-encoding", "connection"]
+                .iter()
+                .any(|framing| name.eq_ignore_ascii_case(framing))
+        });
+        match stream {
+            None if bodiless => {}
+            None => {
+                response.headers.insert("Content-Length".to_string(), response.body.len().to_string());
+            }
+            Some(_) if chunked => {
+                response.headers.insert("Transfer-Encoding".to_string(), "chunked".to_string());
+            }
+            Some(_) => {}
+        }
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        response.headers.insert("Connection".to_string(), connection.to_string());
+
+        let mut message = format!("{} {}\r\n", response.http_version(), response.status_code()).into_bytes();
+        for (name, value) in &response.headers {
+            message.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
         }
-        
-        Ok(result)
+        message.extend_from_slice(b"\r\n");
+        if head_request || bodiless {
+            return self.send(&message).await;
+        }
+        message.extend_from_slice(&response.body);
+        self.send(&message).await?;
+
+        if let Some(stream) = stream {
+            self.write_stream(stream, chunked).await?;
+        }
+        Ok(())
     }
 
+    /// Writes a streamed body after its head has been sent.
+    ///
+    /// Each piece is written out before the next one is polled, so a slow
+    /// client slows the producer down rather than piling up memory. Without
+    /// `chunked`, the body ends when the connection is closed. A stream that
+    /// fails, or panics, leaves the body unterminated, so the client sees it
+    /// as cut off.
+    async fn write_stream(&mut self, mut body: BodyStream, chunked: bool) -> ServerResult<()> {
+        loop {
+            let chunk = match AssertUnwindSafe(body.next()).catch_unwind().await {
+                Ok(Some(chunk)) => chunk?,
+                Ok(None) => break,
+                Err(payload) => {
+                    return Err(ServerError::StreamPanicked { message: panic_message(payload.as_ref()) });
+                }
+            };
+            if chunk.is_empty() {
+                // A zero-sized chunk would end the body early
+                continue;
+            }
+            if chunked {
+                let mut frame = Vec::with_capacity(chunk.len() + 12);
+                frame.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                frame.extend_from_slice(&chunk);
+                frame.extend_from_slice(b"\r\n");
+                self.send(&frame).await?;
+            } else {
+                self.send(&chunk).await?;
+            }
+        }
+        if chunked {
+            self.send(b"0\r\n\r\n").await?;
+        }
+        Ok(())
+    }
 
     /// Writes a UTF-8 string to the connection.
-    pub fn write_str(&mut self, s: &str) -> io::Result<()> {
-        self.write(s.as_bytes())
+    pub async fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.write(s.as_bytes()).await
     }
 
     /// Flush any buffered writes.
-    pub fn flush(&mut self) -> io::Result<()> {
-        self.stream.flush()
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
     }
 
     /// Gracefully close the connection.
-    pub fn close(mut self) -> io::Result<()> {
-        self.flush()?;
-        self.stream.shutdown(Shutdown::Both)
+    pub async fn close(mut self) -> io::Result<()> {
+        self.flush().await?;
+        self.stream.shutdown().await
     }
 
+}
+
+impl Connection<TcpStream> {
     /// Returns the peer’s socket address, useful for logging.
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         self.stream.peer_addr()
     }
 }
 
-impl Drop for Connection {
-    fn drop(&mut self) {
-        if let Err(e) = self.stream.shutdown(Shutdown::Both) {
-            eprintln!("Warning: failed to shutdown connection: {}", e);
-        }
+/// Runs an I/O future, failing with `TimedOut` if it takes longer than `dur`.
+async fn with_timeout<T>(dur: Duration, fut: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    match timeout(dur, fut).await {
+        Ok(res) => res,
+        Err(_) => Err(io::Error::new(ErrorKind::TimedOut, "connection timed out")),
     }
 }
 
-
 fn find_headers_end(data: &[u8]) -> Option<usize> {
     // Look for \r\n\r\n sequence that marks end of HTTP headers
     data.windows(4)
@@ -180,16 +486,226 @@ fn find_headers_end(data: &[u8]) -> Option<usize> {
     .map(|pos| pos + 4)
 }
 
-fn parse_content_length(headers: &[u8]) -> Option<usize> {
-    let header_str = std::str::from_utf8(headers).ok()?;
-    for line in header_str.lines() {
-        if line.to_lowercase().starts_with("content-length:") {
-            return line.split(':')
-                    .nth(1)?
-                    .trim()
-                    .parse()
-                    .ok();
+/// Parses a Content-Length value, which must be nothing but ASCII digits.
+fn parse_content_length(value: &str) -> ServerResult<usize> {
+    let invalid = || ParseError::InvalidContentLength { value: value.to_string() };
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid().into());
+    }
+    value.parse().map_err(|_| invalid().into())
+}
+
+/// Parses a chunk-size line, ignoring any chunk extensions after `;`.
+fn parse_chunk_size(line: &[u8]) -> ServerResult<usize> {
+    let line = std::str::from_utf8(line).map_err(|_| ParseError::InvalidUtf8)?;
+    let size = line.split(';').next().unwrap_or_default().trim();
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::invalid_chunk("invalid chunk size").into());
+    }
+    usize::from_str_radix(size, 16)
+        .map_err(|_| ParseError::invalid_chunk("chunk size too large").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ResponseError;
+    use super::*;
+
+    impl IoStream for tokio::io::DuplexStream {}
+
+    #[test]
+    fn chunk_sizes() {
+        assert_eq!(parse_chunk_size(b"1a").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"0;name=value").unwrap(), 0);
+        assert!(parse_chunk_size(b"").is_err());
+        assert!(parse_chunk_size(b"-1").is_err());
+        assert!(parse_chunk_size(b"fffffffffffffffffffff").is_err());
+    }
+
+    #[tokio::test]
+    async fn decodes_chunked_body() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        client.write_all(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: abc\r\n\r\nGET").await.unwrap();
+
+        let request = connection.read_http_response().await.unwrap().unwrap();
+        assert_eq!(
+            request,
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nChecksum: abc\r\n\r\nhello world"
+        );
+        assert_eq!(connection.pending, b"GET");
+    }
+
+    #[tokio::test]
+    async fn rejects_ambiguous_length() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n").await.unwrap();
+
+        let err = connection.read_http_response().await.unwrap_err();
+        assert!(matches!(err, ServerError::ParseError { source: ParseError::AmbiguousLength }));
+    }
+
+    async fn read_error(request: &[u8]) -> ParseError {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        client.write_all(request).await.unwrap();
+        match connection.read_http_response().await {
+            Err(ServerError::ParseError { source }) => source,
+            res => panic!("expected a parse error, got {:?}", res.map(|_| ())),
         }
     }
-    None
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn rejects_repeated_or_invalid_framing() {
+        let rejected: &[&[u8]] = &[
+            b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nabcde",
+            b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabc",
+            b"POST / HTTP/1.1\r\nContent-Length: +3\r\n\r\nabc",
+            b"POST / HTTP/1.1\r\nContent-Length: 3, 3\r\n\r\nabc",
+            b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        ];
+        for request in rejected {
+            let err = read_error(request).await;
+            assert_eq!(err.status_code(), StatusCode::BadRequest, "{}", String::from_utf8_lossy(request));
+        }
+
+        // A header that is not UTF-8 must not hide the framing headers, or
+        // the body would be read as a second, smuggled request.
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        let smuggled = b"POST / HTTP/1.1\r\nX: \xff\r\nContent-Length: 23\r\n\r\nGET /admin HTTP/1.1\r\n\r\n";
+        client.write_all(smuggled).await.unwrap();
+        let request = connection.read_http_response().await.unwrap().unwrap();
+        assert_eq!(request, smuggled);
+        assert!(connection.pending.is_empty());
+        let err = read_error(b"POST / HTTP/1.1\r\nContent-Length: 2\xff\r\n\r\nab").await;
+        assert_eq!(err.status_code(), StatusCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_transfer_codings() {
+        for encoding in ["gzip, chunked", "gzip", "chunked, chunked"] {
+            let request = format!("POST / HTTP/1.1\r\nTransfer-Encoding: {}\r\n\r\n0\r\n\r\n", encoding);
+            let err = read_error(request.as_bytes()).await;
+            assert_eq!(err.status_code(), StatusCode::NotImplemented, "{}", encoding);
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_expect_continue() {
+        let config = ServerConfig { max_request_size: 1024, ..Default::default() };
+        let send = |head: &'static [u8]| {
+            let config = config.clone();
+            async move {
+                let (mut client, server) = tokio::io::duplex(4096);
+                let mut connection = Connection::new(server, &config).unwrap();
+                client.write_all(head).await.unwrap();
+                let read = tokio::time::timeout(Duration::from_millis(100), connection.read_http_response()).await;
+                drop(connection);
+                let mut written = Vec::new();
+                client.read_to_end(&mut written).await.unwrap();
+                (read, written)
+            }
+        };
+
+        let (_, written) = send(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").await;
+        assert_eq!(written, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+        // HTTP/1.0 clients do not know 100 Continue.
+        let (_, written) = send(b"POST / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").await;
+        assert!(written.is_empty());
+
+        // Too large a body is refused without asking for it.
+        let (read, written) = send(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 1000000000\r\n\r\n").await;
+        assert!(matches!(read, Ok(Err(ServerError::ParseError { source: ParseError::BufferOverflow { .. } }))));
+        assert!(written.is_empty());
+    }
+
+    #[tokio::test]
+    async fn limits_the_head_size() {
+        let config = ServerConfig { max_head_size: 64, ..Default::default() };
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &config).unwrap();
+        // Split so the terminator straddles two reads.
+        client.write_all(b"GET / HTTP/1.1\r\nX: 1\r\n\r").await.unwrap();
+        let read = tokio::spawn(async move {
+            let request = connection.read_http_response().await.unwrap().unwrap();
+            (connection, request)
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        client.write_all(b"\n").await.unwrap();
+        let (mut connection, request) = read.await.unwrap();
+        assert_eq!(request, b"GET / HTTP/1.1\r\nX: 1\r\n\r\n");
+
+        client.write_all(format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(64)).as_bytes()).await.unwrap();
+        let err = connection.read_http_response().await.unwrap_err();
+        assert!(matches!(err, ServerError::ParseError { source: ParseError::HeadTooLarge { limit: 64 } }));
+    }
+
+    #[tokio::test]
+    async fn reports_truncated_body() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc").await.unwrap();
+        drop(client);
+
+        let err = connection.read_http_response().await.unwrap_err();
+        assert!(matches!(
+            err,
+            ServerError::ParseError { source: ParseError::ContentLengthMismatch { expected: 10, actual: 3 } }
+        ));
+    }
+
+    #[tokio::test]
+    async fn writes_binary_body_intact() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        let body = vec![0x89, b'P', b'N', b'G', 0xff, 0x00];
+        let response = HttpResponse::new().header("Content-Length", "99").body(body.clone());
+        connection.write_response(response, true, false, false).await.unwrap();
+        drop(connection);
+
+        let mut written = Vec::new();
+        client.read_to_end(&mut written).await.unwrap();
+        let head_end = find_headers_end(&written).unwrap();
+        let head = std::str::from_utf8(&written[..head_end]).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Length: 6\r\n"));
+        assert!(head.contains("Connection: close\r\n"));
+        assert_eq!(&written[head_end..], &body[..]);
+    }
+
+    #[tokio::test]
+    async fn leaves_framing_off_bodiless_statuses() {
+        for status in [StatusCode::Continue, StatusCode::NoContent, StatusCode::NotModified] {
+            let (mut client, server) = tokio::io::duplex(1024);
+            let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+            let response = HttpResponse::new().status(status).header("Content-Length", "5").body("stray");
+            connection.write_response(response, true, false, false).await.unwrap();
+            drop(connection);
+
+            let mut written = String::new();
+            client.read_to_string(&mut written).await.unwrap();
+            assert!(!written.contains("Content-Length"), "{}", written);
+            assert!(!written.contains("Transfer-Encoding"), "{}", written);
+            assert!(written.ends_with("\r\n\r\n"), "{}", written);
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_chunked_stream() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(server, &ServerConfig::default()).unwrap();
+        let chunks = ["hello", "", " world"].map(|c| Ok::<_, io::Error>(bytes::Bytes::from(c)));
+        let body = BodyStream::new(futures_util::stream::iter(chunks));
+        connection.write_stream(body, true).await.unwrap();
+        drop(connection);
+
+        let mut written = Vec::new();
+        client.read_to_end(&mut written).await.unwrap();
+        assert_eq!(written, b"5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n");
+    }
+}
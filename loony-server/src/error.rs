@@ -1,3 +1,6 @@
+use crate::params::ParamsError;
+use crate::response::{HttpResponse, StatusCode};
+
 /// Comprehensive error types for the HTTP server
 #[derive(Debug, thiserror::Error)]
 pub enum ServerError {
@@ -46,6 +49,10 @@ pub enum ServerError {
     ResourceNotFound {
         resource: String,
     },
+    #[error("Response body panicked: {message}")]
+    StreamPanicked {
+        message: String,
+    },
 }
 
 /// Connection-level errors
@@ -96,6 +103,10 @@ pub enum ParseError {
         attempted: usize,
         capacity: usize,
     },
+    #[error("Request head is larger than {limit} bytes")]
+    HeadTooLarge {
+        limit: usize,
+    },
     #[error("Invalid UTF-8 sequence in request")]
     InvalidUtf8,
     #[error("Content length mismatch: expected {expected}, got {actual}")]
@@ -103,6 +114,24 @@ pub enum ParseError {
         expected: usize,
         actual: usize,
     },
+    #[error("Request has both Content-Length and Transfer-Encoding")]
+    AmbiguousLength,
+    #[error("Invalid Content-Length: {value:?}")]
+    InvalidContentLength {
+        value: String,
+    },
+    #[error("Unsupported Transfer-Encoding: {encoding}")]
+    UnsupportedTransferEncoding {
+        encoding: String,
+    },
+    #[error("Invalid chunked body: {reason}")]
+    InvalidChunk {
+        reason: String,
+    },
+    #[error("Invalid query string: {reason}")]
+    InvalidQuery {
+        reason: String,
+    },
 }
 
 /// Request handling errors
@@ -134,6 +163,41 @@ pub enum HandlerError {
     InternalError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+    #[error("No app data of type `{type_name}` was registered")]
+    MissingData {
+        type_name: &'static str,
+    },
+    #[error("Invalid path segment `{name}` = {value:?}: {reason}")]
+    InvalidPathSegment {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    #[error("Invalid path: {message}")]
+    InvalidPath {
+        message: String,
+    },
+    #[error("Invalid query: {message}")]
+    InvalidQuery {
+        message: String,
+    },
+    #[error("Invalid JSON: {message}")]
+    InvalidJson {
+        message: String,
+    },
+    #[error("Invalid form: {message}")]
+    InvalidForm {
+        message: String,
+    },
+    #[error("Invalid multipart body: {message}")]
+    InvalidMultipart {
+        message: String,
+    },
+    #[error("Field `{name}` is larger than {limit} bytes")]
+    FieldTooLarge {
+        name: String,
+        limit: usize,
+    },
 }
 
 /// Service factory errors
@@ -156,6 +220,7 @@ pub type ServerResult<T> = Result<T, ServerError>;
 pub type ConnectionResult<T> = Result<T, ConnectionError>;
 pub type ParseResult<T> = Result<T, ParseError>;
 pub type HandlerResult<T> = Result<T, HandlerError>;
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 // Implementation for converting various error types
 impl From<Box<dyn std::error::Error + Send + Sync>> for ServerError {
@@ -218,6 +283,144 @@ impl HandlerError {
     }
 }
 
+impl HandlerError {
+    /// A path param that does not parse is a resource that does not exist;
+    /// any other mismatch is a bad request.
+    pub fn invalid_path(err: ParamsError) -> Self {
+        match err {
+            ParamsError::Value { name, value, reason } => HandlerError::InvalidPathSegment { name, value, reason },
+            ParamsError::Custom(message) => HandlerError::InvalidPath { message },
+        }
+    }
+
+    pub fn invalid_query<E: std::fmt::Display>(err: E) -> Self {
+        HandlerError::InvalidQuery {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// An error that knows how to answer the request it failed.
+pub trait ResponseError: std::error::Error {
+    /// The status the error is answered with. Defaults to 500.
+    fn status_code(&self) -> StatusCode {
+        StatusCode::InternalServerError
+    }
+
+    /// The response sent for the error: by default, its status with the
+    /// error message as a plain text body. A server error only gets the
+    /// reason phrase, since its message is for the logs, not the client.
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let body = if status.is_server_error() {
+            status.reason_phrase().to_string()
+        } else {
+            self.to_string()
+        };
+        HttpResponse::new()
+            .status(status)
+            .content_type("text/plain; charset=utf-8")
+            .body(body)
+    }
+}
+
+impl ResponseError for HandlerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            HandlerError::MissingUri
+            | HandlerError::InvalidPath { .. }
+            | HandlerError::InvalidQuery { .. }
+            | HandlerError::InvalidJson { .. }
+            | HandlerError::InvalidForm { .. }
+            | HandlerError::InvalidMultipart { .. } => StatusCode::BadRequest,
+            HandlerError::RouteNotFound { .. } | HandlerError::InvalidPathSegment { .. } => StatusCode::NotFound,
+            HandlerError::MethodNotAllowed { .. } => StatusCode::MethodNotAllowed,
+            HandlerError::ServiceUnavailable { .. } => StatusCode::ServiceUnavailable,
+            HandlerError::PayloadTooLarge { .. } | HandlerError::FieldTooLarge { .. } => StatusCode::PayloadTooLarge,
+            HandlerError::UnsupportedMediaType { .. } => StatusCode::UnsupportedMediaType,
+            HandlerError::InternalError { .. } | HandlerError::MissingData { .. } => StatusCode::InternalServerError,
+        }
+    }
+}
+
+impl ResponseError for ParseError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ParseError::BufferOverflow { .. } => StatusCode::PayloadTooLarge,
+            ParseError::HeadTooLarge { .. } => StatusCode::RequestHeaderFieldsTooLarge,
+            ParseError::UnsupportedTransferEncoding { .. } => StatusCode::NotImplemented,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl ResponseError for ParamsError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BadRequest
+    }
+}
+
+impl ResponseError for std::io::Error {}
+
+impl ResponseError for serde_json::Error {}
+
+/// The error carried through `Service::Error`: any `ResponseError`, turned
+/// into a response once it reaches the server.
+pub struct Error {
+    inner: Box<dyn ResponseError>,
+}
+
+impl Error {
+    pub fn as_response_error(&self) -> &dyn ResponseError {
+        &*self.inner
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.inner.status_code()
+    }
+
+    pub fn error_response(&self) -> HttpResponse {
+        self.inner.error_response()
+    }
+}
+
+impl<E: ResponseError + 'static> From<E> for Error {
+    fn from(err: E) -> Self {
+        Error { inner: Box::new(err) }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl ConnectionError {
+    /// Classifies a failed read from the client.
+    pub fn read(source: std::io::Error) -> Self {
+        match source.kind() {
+            std::io::ErrorKind::UnexpectedEof => ConnectionError::ConnectionClosed,
+            std::io::ErrorKind::TimedOut => ConnectionError::Timeout,
+            _ => ConnectionError::ReadError { source },
+        }
+    }
+
+    /// Classifies a failed write to the client.
+    pub fn write(source: std::io::Error) -> Self {
+        match source.kind() {
+            std::io::ErrorKind::TimedOut => ConnectionError::Timeout,
+            _ => ConnectionError::WriteError { source },
+        }
+    }
+}
+
 impl ParseError {
     pub fn buffer_overflow(attempted: usize, capacity: usize) -> Self {
         ParseError::BufferOverflow {
@@ -231,4 +434,26 @@ impl ParseError {
             reason: reason.to_string(),
         }
     }
-}
\ No newline at end of file
+
+    pub fn invalid_chunk(reason: &str) -> Self {
+        ParseError::InvalidChunk {
+            reason: reason.to_string(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_errors_hide_their_message() {
+        let err = std::io::Error::other("/var/lib/app/secret.db: permission denied");
+        let response = err.error_response();
+        assert_eq!(response.status_code(), StatusCode::InternalServerError);
+        assert_eq!(response.body, "Internal Server Error");
+
+        let response = HandlerError::invalid_query("bad escape").error_response();
+        assert_eq!(response.status_code(), StatusCode::BadRequest);
+        assert!(std::str::from_utf8(&response.body).unwrap().contains("bad escape"));
+    }
+}
@@ -4,148 +4,278 @@ use std::marker::PhantomData;
 use crate::service::{ServiceRequest, ServiceResponse};
 use pin_project::pin_project;
 use futures_util::ready as fut_ready;
+use futures_util::future::{TryMaybeDone, try_maybe_done};
 use std::{pin::Pin, task::Poll};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+
+use crate::error::{Error, HandlerError, ParseError};
+use crate::params::{from_params, percent_decode};
+use crate::query::QueryMap;
 
 use loony_service::{Service, ServiceFactory};
 
-pub trait FromPathSegments: Clone {
-    fn from_segments(segments: &[&str]) -> Option<Self>
-    where
-        Self: Sized;
+pub trait FromRequest: Sized {
+    type Future: Future<Output=Result<Self, HandlerError>>;
+    fn from_request(req: &ServiceRequest) -> Self::Future;
 }
 
-
-impl FromPathSegments for i32 {
-    fn from_segments(segments: &[&str]) -> Option<Self> {
-        if segments.len() < 1 { return None; }
-        let id = segments[0].parse().ok()?;
-        Some(id)
+impl FromRequest for () {
+    type Future = Ready<Result<(), HandlerError>>;
+    fn from_request(_: &ServiceRequest) -> Self::Future {
+        ready(Ok(()))
     }
 }
 
-impl FromPathSegments for (i32, String) {
-    fn from_segments(segments: &[&str]) -> Option<Self> {
-        if segments.len() < 2 { return None; }
-        let id = segments[0].parse().ok()?;
-        let name = segments[1].to_string();
-        Some((id, name))
+impl FromRequest for String {
+    type Future = Ready<Result<String, HandlerError>>;
+
+    fn from_request(req: &ServiceRequest) -> Self::Future {
+        ready(req.req.uri.clone().ok_or(HandlerError::MissingUri))
     }
 }
 
-impl FromPathSegments for (i32, i32) {
-    fn from_segments(segments: &[&str]) -> Option<Self> {
-        if segments.len() < 2 { return None; }
-        Some((segments[0].parse().ok()?, segments[1].parse().ok()?))
+/// Extracts the raw request body.
+impl FromRequest for Bytes {
+    type Future = Ready<Result<Bytes, HandlerError>>;
+
+    fn from_request(req: &ServiceRequest) -> Self::Future {
+        ready(Ok(req.req.body.clone()))
     }
 }
 
-impl FromPathSegments for (String, String) {
-    fn from_segments(segments: &[&str]) -> Option<Self> {
-        if segments.len() < 2 { return None; }
-        Some((segments[0].to_string(), segments[1].to_string()))
+#[derive(Clone)]
+pub struct Data<T>(pub T);
+
+/// Params captured from the path by the router, deserialized into `T`.
+///
+/// A scalar takes the only param, a tuple takes them in the order they
+/// appear in the route, and a struct is matched by `:name`. A param that
+/// fails to parse is answered with 404, any other mismatch with 400.
+#[derive(Clone)]
+pub struct Path<T>(pub T);
+
+impl<T> FromRequest for Data<T> 
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Future = Ready<Result<Data<T>, HandlerError>>;
+
+    /// Fails with 500 if no `T` was registered with `App::data`.
+    fn from_request(req: &ServiceRequest) -> Self::Future {
+        let data = req.extensions.get::<T>().cloned().map(Data).ok_or_else(|| HandlerError::MissingData {
+            type_name: std::any::type_name::<T>(),
+        });
+        ready(data)
     }
 }
 
-impl FromPathSegments for (i32, String, String) {
-    fn from_segments(segments: &[&str]) -> Option<Self> {
-        if segments.len() < 3 { return None; }
-        Some((segments[0].parse().ok()?, segments[1].to_string(), segments[2].to_string()))
+impl<P> FromRequest for Path<P>
+where
+    P: DeserializeOwned,
+{
+    type Future = Ready<Result<Path<P>, HandlerError>>;
+
+    fn from_request(req: &ServiceRequest) -> Self::Future {
+        ready(path_from_request(req))
     }
 }
 
-pub trait FromRequest: Clone {
-    type Future: Future<Output=Result<Self, ()>>;
-    fn from_request(req: &ServiceRequest) -> Self::Future;
+pub use crate::multipart::{Multipart, MultipartConfig};
+pub use crate::responder::Json;
+
+/// Settings for the `Json` extractor, registered with `App::app_data`.
+#[derive(Debug, Clone)]
+pub struct JsonConfig {
+    limit: usize,
 }
 
-impl FromRequest for () {
-    type Future = Ready<Result<(), ()>>;
-    fn from_request(_: &ServiceRequest) -> Self::Future {
-        ready(Ok(()))
+impl JsonConfig {
+    /// The largest body accepted, in bytes. Defaults to 2 MiB.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
     }
 }
 
-impl FromRequest for (String, ) {
-    type Future = Ready<Result<(String,), ()>>;
-    fn from_request(_: &ServiceRequest) -> Self::Future {
-        ready(Ok(("".to_string(), )))
+impl Default for JsonConfig {
+    fn default() -> Self {
+        JsonConfig { limit: 2 * 1024 * 1024 }
     }
 }
 
-impl FromRequest for String {
-    type Future = Ready<Result<String, ()>>;
+/// Deserializes a `Content-Type: application/json` (or `+json`) body.
+/// Answers 415 for another content type, 413 for a body over the
+/// `JsonConfig` limit and 400, with the line and column, for invalid JSON.
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    type Future = Ready<Result<Json<T>, HandlerError>>;
 
     fn from_request(req: &ServiceRequest) -> Self::Future {
-        ready(Ok(req.req.uri.clone().unwrap()))
+        let limit = req.extensions.get::<JsonConfig>().map_or(JsonConfig::default().limit, |c| c.limit);
+        let json = check_content_type(req, |mime| mime == "application/json" || mime.ends_with("+json"))
+            .and_then(|_| check_limit(req, limit))
+            .and_then(|_| {
+                serde_json::from_slice(&req.req.body).map(Json).map_err(|e| HandlerError::InvalidJson {
+                    message: e.to_string(),
+                })
+            });
+        ready(json)
     }
 }
 
-#[derive(Clone)]
-pub struct Data<T>(pub T);
+/// An `application/x-www-form-urlencoded` body deserialized into `T`, the
+/// same way `Query` reads the query string.
+///
+/// Answers 415 for another content type, 413 for a body over the
+/// `FormConfig` limit and 400 for malformed input.
+pub struct Form<T>(pub T);
+
+/// Settings for the `Form` extractor, registered with `App::app_data`.
+#[derive(Debug, Clone)]
+pub struct FormConfig {
+    limit: usize,
+}
 
-#[derive(Clone)]
-pub struct Path<T>(pub T);
+impl FormConfig {
+    /// The largest body accepted, in bytes. Defaults to 16 KiB.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
 
-// impl<T> FromRequest for Data<T>
-// where
-//     T: Clone + Send + Sync + 'static,
-// {
-//     type Future = Ready<Result<Data<T>, ()>>;
-//     fn from_request(req: &ServiceRequest) -> Self::Future {
-//         let a = req.0.extensions.get::<T>().unwrap();
-//         ready(Ok(Data(a.clone())))
-//     }
-// }
+impl Default for FormConfig {
+    fn default() -> Self {
+        FormConfig { limit: 16 * 1024 }
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    type Future = Ready<Result<Form<T>, HandlerError>>;
 
-impl<T> FromRequest for Data<T> 
-where
-    T: Clone + Send + Sync + 'static,
-{
-    type Future = Ready<Result<Data<T>, ()>>;
     fn from_request(req: &ServiceRequest) -> Self::Future {
-        let a = req.extensions.get::<T>().unwrap();
-        ready(Ok(Data(a.clone())))
+        let limit = req.extensions.get::<FormConfig>().map_or(FormConfig::default().limit, |c| c.limit);
+        ready(
+            check_content_type(req, |mime| mime == "application/x-www-form-urlencoded")
+                .and_then(|_| check_limit(req, limit))
+                .and_then(|_| form_from_body(&req.req.body)),
+        )
     }
 }
 
-impl<T> FromRequest for (Data<T>, ) 
-where
-    T: Clone + Send + Sync + 'static,
-{
-    type Future = Ready<Result<(Data<T>, ), ()>>;
-    fn from_request(req: &ServiceRequest) -> Self::Future {
-        let a = req.extensions.get::<T>().unwrap();
-        ready(Ok((Data(a.clone()), )))
+fn form_from_body<T: DeserializeOwned>(body: &[u8]) -> Result<Form<T>, HandlerError> {
+    let invalid = |message: String| HandlerError::InvalidForm { message };
+    let body = std::str::from_utf8(body).map_err(|_| invalid("body is not UTF-8".to_string()))?;
+    let fields = match QueryMap::parse(body) {
+        Ok(fields) => fields,
+        Err(ParseError::InvalidQuery { reason }) => return Err(invalid(reason)),
+        Err(e) => return Err(invalid(e.to_string())),
+    };
+    from_params(fields.pairs()).map(Form).map_err(|e| invalid(e.to_string()))
+}
+
+/// Fails with 415 unless the request's media type, lowercased and without
+/// parameters, is accepted by `accept`.
+pub(crate) fn check_content_type(req: &ServiceRequest, accept: impl Fn(&str) -> bool) -> Result<(), HandlerError> {
+    let content_type = req.req.header("Content-Type").unwrap_or_default();
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if accept(&mime) {
+        Ok(())
+    } else {
+        Err(HandlerError::UnsupportedMediaType {
+            content_type: if content_type.is_empty() { "none".to_string() } else { content_type.to_string() },
+        })
     }
 }
 
-impl<T> FromRequest for (Data<T>, String,) 
-where
-    T: Clone + Send + Sync + 'static,
-{
-    type Future = Ready<Result<(Data<T>, String,), ()>>;
-    fn from_request(req: &ServiceRequest) -> Self::Future {
-        let a = req.extensions.get::<T>().unwrap();
-        return ready(Ok((Data(a.clone()), "".to_string(),)));
+/// Fails with 413 when the body is larger than `limit` bytes.
+pub(crate) fn check_limit(req: &ServiceRequest, limit: usize) -> Result<(), HandlerError> {
+    match req.req.body.len() {
+        size if size > limit => Err(HandlerError::PayloadTooLarge { size }),
+        _ => Ok(()),
     }
 }
 
-impl<T, P> FromRequest for (Data<T>, Path<P>,)
-where
-    T: Clone + Send + Sync + 'static,
-    P: FromPathSegments + Clone,
-{
-    type Future = Ready<Result<(Data<T>, Path<P>), ()>>;
+/// Extracts every element of the tuple, failing with the first error.
+macro_rules! tuple_from_request {
+    ($fut:ident; $($T:ident $t:ident),+) => {
+        impl<$($T: FromRequest),+> FromRequest for ($($T,)+) {
+            type Future = $fut<$($T),+>;
 
-    fn from_request(req: &ServiceRequest) -> Self::Future {
-        let a = req.extensions.get::<T>().unwrap();
-        let path = req.req.uri.clone().unwrap();
-        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+            fn from_request(req: &ServiceRequest) -> Self::Future {
+                $fut {
+                    $($t: try_maybe_done($T::from_request(req)),)+
+                }
+            }
+        }
+
+        #[pin_project]
+        pub struct $fut<$($T: FromRequest),+> {
+            $(#[pin] $t: TryMaybeDone<$T::Future>,)+
+        }
+
+        impl<$($T: FromRequest),+> Future for $fut<$($T),+> {
+            type Output = Result<($($T,)+), HandlerError>;
 
-        match P::from_segments(&segments[2..]) {
-            Some(p) => ready(Ok((Data(a.clone()), Path(p)))),
-            None => ready(Err(())),
+            fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+                let mut this = self.project();
+                let mut done = true;
+                $(done &= this.$t.as_mut().poll(cx)?.is_ready();)+
+                if !done {
+                    return Poll::Pending;
+                }
+                Poll::Ready(Ok(($(this.$t.take_output().expect("extractor polled after completion"),)+)))
+            }
         }
+    };
+}
+
+tuple_from_request!(TupleFromRequest1; A a);
+tuple_from_request!(TupleFromRequest2; A a, B b);
+tuple_from_request!(TupleFromRequest3; A a, B b, C c);
+tuple_from_request!(TupleFromRequest4; A a, B b, C c, D d);
+tuple_from_request!(TupleFromRequest5; A a, B b, C c, D d, E e);
+tuple_from_request!(TupleFromRequest6; A a, B b, C c, D d, E e, F f);
+tuple_from_request!(TupleFromRequest7; A a, B b, C c, D d, E e, F f, G g);
+tuple_from_request!(TupleFromRequest8; A a, B b, C c, D d, E e, F f, G g, H h);
+tuple_from_request!(TupleFromRequest9; A a, B b, C c, D d, E e, F f, G g, H h, I i);
+tuple_from_request!(TupleFromRequest10; A a, B b, C c, D d, E e, F f, G g, H h, I i, J j);
+tuple_from_request!(TupleFromRequest11; A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k);
+tuple_from_request!(TupleFromRequest12; A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l);
+
+/// Deserializes a `Path` from the percent-decoded params the router captured.
+/// A segment with a broken escape matches nothing, so it is a 404.
+fn path_from_request<P: DeserializeOwned>(req: &ServiceRequest) -> Result<Path<P>, HandlerError> {
+    let params = req.req.path_params.iter()
+        .map(|(name, value)| match percent_decode(value, false) {
+            Ok(decoded) => Ok((name.clone(), decoded.into_owned())),
+            Err(reason) => Err(HandlerError::InvalidPathSegment {
+                name: name.clone(),
+                value: value.clone(),
+                reason: reason.to_string(),
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    from_params(&params).map(Path).map_err(HandlerError::invalid_path)
+}
+
+/// The query string deserialized into `T`.
+///
+/// Fields are matched by name; a name given more than once fills a `Vec`
+/// and an `Option` field may be left out. Malformed input is answered with
+/// 400.
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    type Future = Ready<Result<Query<T>, HandlerError>>;
+
+    fn from_request(req: &ServiceRequest) -> Self::Future {
+        let query = match req.req.query() {
+            Ok(query) => query,
+            Err(ParseError::InvalidQuery { reason }) => return ready(Err(HandlerError::InvalidQuery { message: reason })),
+            Err(e) => return ready(Err(HandlerError::invalid_query(e))),
+        };
+        ready(from_params(query.pairs()).map(Query).map_err(HandlerError::invalid_query))
     }
 }
 
@@ -168,12 +298,12 @@ where
     S: Service<
         Request=(T, ServiceRequest),
         Response=ServiceResponse,
-        Error=()
+        Error = Error
     > + Clone
 {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
+    type Error = Error;
     type Config = ();
     type Service = ExtractService<T, S>;
     type InitError = ();
@@ -198,12 +328,12 @@ where
     S: Service<
         Request=(T, ServiceRequest),
         Response=ServiceResponse,
-        Error=()
+        Error = Error
     > + Clone
 {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
+    type Error = Error;
     type Future = ExtractResponse<T, S>;
 
     fn call(&mut self, req: Self::Request) -> Self::Future {
@@ -231,10 +361,10 @@ where
     S: Service<
         Request = (T, ServiceRequest),
         Response = ServiceResponse,
-        Error=()
+        Error = Error
     >,
 {
-    type Output = Result<ServiceResponse, ()>;
+    type Output = Result<ServiceResponse, Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
         let this = self.as_mut().project();
@@ -248,8 +378,8 @@ where
         }
 
         match fut_ready!(this.fut.poll(cx)) {
-            Err(_) => {
-                Poll::Ready(Err(()))
+            Err(e) => {
+                Poll::Ready(Err(e.into()))
             }
             Ok(data) => {
                 let l = this.service.call((data, this.req.clone()));
@@ -303,3 +433,89 @@ where
 // macro_rules! replace_expr {
 //     ($_t:ty, $sub:expr) => { $sub };
 // }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use crate::error::ResponseError;
+    use crate::extensions::Extensions;
+    use crate::request::HttpRequest;
+    use crate::response::StatusCode;
+    use std::rc::Rc;
+    use super::*;
+
+    fn request(params: &[(&str, &str)]) -> ServiceRequest {
+        let mut req = HttpRequest::new();
+        req.uri = Some("/users/7".to_string());
+        req.body = Bytes::from_static(b"payload");
+        req.path_params = params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        ServiceRequest { req, extensions: Rc::new(Extensions::new()) }
+    }
+
+    #[test]
+    fn extracts_tuples_in_any_order() {
+        let req = request(&[("id", "7")]);
+        let (body, Path(id), uri) = block_on(<(Bytes, Path<u32>, String)>::from_request(&req)).ok().unwrap();
+        assert_eq!((&body[..], id, uri.as_str()), (&b"payload"[..], 7, "/users/7"));
+    }
+
+    #[derive(serde::Deserialize)]
+    struct User {
+        name: String,
+    }
+
+    fn json_request(content_type: &str, body: &'static [u8], limit: usize) -> ServiceRequest {
+        let mut req = request(&[]);
+        req.req.headers.push(("Content-Type".to_string(), content_type.to_string()));
+        req.req.body = Bytes::from_static(body);
+        let mut extensions = Extensions::new();
+        extensions.insert(JsonConfig::default().limit(limit));
+        req.extensions = Rc::new(extensions);
+        req
+    }
+
+    #[test]
+    fn json_checks_type_size_and_syntax() {
+        let extract = |req| block_on(Json::<User>::from_request(&req)).map(|Json(user)| user.name);
+        let status = |req| extract(req).unwrap_err().status_code();
+        assert_eq!(extract(json_request("application/json; charset=utf-8", br#"{"name":"loony"}"#, 64)).ok().as_deref(), Some("loony"));
+        assert_eq!(status(json_request("text/plain", br#"{"name":"loony"}"#, 64)), StatusCode::UnsupportedMediaType);
+        assert_eq!(status(json_request("application/json", br#"{"name":"loony"}"#, 8)), StatusCode::PayloadTooLarge);
+        match extract(json_request("application/json", b"{\n\"name\": 1}", 64)) {
+            Err(HandlerError::InvalidJson { message }) => assert!(message.contains("line 2"), "{}", message),
+            _ => panic!("expected invalid JSON"),
+        }
+    }
+
+    #[test]
+    fn form_shares_the_query_parser() {
+        let mut req = json_request("application/x-www-form-urlencoded", b"name=loony+server&x=%21", 64);
+        req.extensions = Rc::new(Extensions::new());
+        let Form(user) = block_on(Form::<User>::from_request(&req)).ok().unwrap();
+        assert_eq!(user.name, "loony server");
+
+        req.req.body = Bytes::from_static(b"name=%zz");
+        let err = block_on(Form::<User>::from_request(&req)).err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::BadRequest);
+
+        req.req.headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        let err = block_on(Form::<User>::from_request(&req)).err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::UnsupportedMediaType);
+    }
+
+    #[test]
+    fn missing_data_is_a_server_error() {
+        let err = block_on(Data::<u32>::from_request(&request(&[]))).err().unwrap();
+        assert!(matches!(err, HandlerError::MissingData { type_name: "u32" }));
+        assert_eq!(err.status_code(), StatusCode::InternalServerError);
+    }
+
+    #[test]
+    fn tuple_fails_with_first_error() {
+        let req = request(&[("id", "seven")]);
+        match block_on(<(String, Path<u32>)>::from_request(&req)) {
+            Err(HandlerError::InvalidPathSegment { name, .. }) => assert_eq!(name, "id"),
+            _ => panic!("expected an invalid path segment"),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+
+use tokio::sync::{mpsc, watch};
+
+/// What a worker has been told to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Shutdown {
+    Running,
+    /// Stop accepting, let in-flight requests finish.
+    Graceful,
+    /// Drop every connection right away.
+    Immediate,
+}
+
+/// A handle to a running `HttpServer`.
+///
+/// Handles are cheap to clone and can be moved to other threads. Awaiting a
+/// handle waits until the server has fully stopped.
+#[derive(Clone)]
+pub struct ServerHandle {
+    pub(crate) commands: mpsc::UnboundedSender<bool>,
+    pub(crate) stopped: watch::Receiver<bool>,
+}
+
+impl ServerHandle {
+    /// Stops the server and waits until it has shut down.
+    ///
+    /// A graceful stop closes the listeners, lets in-flight requests and
+    /// keep-alive connections finish within the configured shutdown timeout,
+    /// then runs the `App` shutdown hooks. Otherwise connections are dropped
+    /// right away. A forced stop can follow a graceful one still in progress.
+    pub async fn stop(&self, graceful: bool) {
+        let _ = self.commands.send(graceful);
+        self.clone().await
+    }
+}
+
+impl IntoFuture for ServerHandle {
+    type Output = ();
+    type IntoFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let mut stopped = self.stopped;
+        Box::pin(async move {
+            // An error means the controller is gone, so nothing is running.
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+        })
+    }
+}
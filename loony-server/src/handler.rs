@@ -2,6 +2,7 @@ use std::future::Future;
 use std::marker::PhantomData;
 use std::{ pin::Pin, task::Poll};
 
+use crate::error::Error;
 use crate::responder::Responder;
 use loony_service::{Service};
 use crate::service::{ServiceRequest, ServiceResponse};
@@ -14,38 +15,36 @@ where
     fn call(&self, param: P) -> R;
 }
 
-impl<T, R, O> Factory<(), R, O> for T 
-where
-    T: Fn() -> R + Clone + 'static,
-    R: Future<Output=O>,
-    O: Responder 
-{
-    fn call(&self, _: ()) -> R {
-        (self)()
-    }
-}
-
-impl<T, PA, R, O> Factory<(PA,), R, O> for T 
-where
-    T: Fn(PA,) -> R + Clone + 'static,
-    R: Future<Output=O>,
-    O: Responder 
-{
-    fn call(&self, (pa,): (PA,)) -> R {
-        (self)(pa)
-    }
+/// Implements `Factory` for handlers taking the given arguments, each of
+/// which is extracted from the request.
+macro_rules! factory_tuple {
+    ($($T:ident $t:ident),*) => {
+        impl<Func, $($T,)* R, O> Factory<($($T,)*), R, O> for Func
+        where
+            Func: Fn($($T),*) -> R + Clone + 'static,
+            R: Future<Output=O>,
+            O: Responder,
+        {
+            fn call(&self, ($($t,)*): ($($T,)*)) -> R {
+                (self)($($t),*)
+            }
+        }
+    };
 }
 
-impl<T, PA, PB, R, O> Factory<(PA,PB), R, O> for T 
-where
-    T: Fn(PA,PB,) -> R + Clone + 'static,
-    R: Future<Output=O>,
-    O: Responder 
-{
-    fn call(&self, (pa,pb,): (PA,PB)) -> R {
-        (self)(pa, pb)
-    }
-}
+factory_tuple!();
+factory_tuple!(A a);
+factory_tuple!(A a, B b);
+factory_tuple!(A a, B b, C c);
+factory_tuple!(A a, B b, C c, D d);
+factory_tuple!(A a, B b, C c, D d, E e);
+factory_tuple!(A a, B b, C c, D d, E e, F f);
+factory_tuple!(A a, B b, C c, D d, E e, F f, G g);
+factory_tuple!(A a, B b, C c, D d, E e, F f, G g, H h);
+factory_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i);
+factory_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j);
+factory_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k);
+factory_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l);
 
 pub struct Handler<T, P, R, O> 
 where
@@ -93,7 +92,7 @@ where
 {
     type Request = (P, ServiceRequest);
     type Response = ServiceResponse;
-    type Error = ();
+    type Error = Error;
     type Future = HandlerServiceResponse<R, O>;
 
     fn call(&mut self, (param, _): (P, ServiceRequest)) -> Self::Future {
@@ -143,14 +142,14 @@ where
     R: Future<Output = O>,
     O: Responder,
 {
-    type Output = Result<ServiceResponse, ()>;
+    type Output = Result<ServiceResponse, Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
         let this = self.as_mut()._project();
         if let Some(fut) = this.fut2.as_pin_mut() {
             return match fut.poll(cx) {
                 Poll::Ready(res) => {
-                    Poll::Ready(Ok(res))
+                    Poll::Ready(res)
                 }
                 Poll::Pending => Poll::Pending,
             };
@@ -12,9 +12,16 @@ pub mod app_service;
 pub mod error;
 pub mod connection;
 pub mod router;
+pub mod params;
+pub mod query;
+pub mod multipart;
 
 mod server;
+mod handle;
+mod listener;
 mod app;
 
-pub use app::App;
-pub use server::HttpServer;
\ No newline at end of file
+pub use app::{App, HandlerPanic};
+pub use error::{Error, ResponseError};
+pub use handle::ServerHandle;
+pub use server::{HttpServer, ServerConfig};
\ No newline at end of file
@@ -0,0 +1,155 @@
+use std::{io, net::SocketAddr};
+
+use socket2::{Domain, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::connection::IoStream;
+
+/// A bound listening socket, before it is handed to a worker.
+pub(crate) enum Listener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Uds(std::os::unix::net::UnixListener, std::path::PathBuf),
+}
+
+impl Listener {
+    /// Binds a TCP listener to `addr`. IPv6 addresses are bound dual-stack.
+    pub(crate) fn bind_tcp(addr: SocketAddr, backlog: i32) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        socket.set_reuse_address(true)?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(false)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(backlog)?;
+        Ok(Listener::Tcp(socket.into()))
+    }
+
+    /// Binds a Unix domain socket at `path`.
+    ///
+    /// A socket file left behind by a server that is no longer running is
+    /// removed first; a live one, or any other kind of file, is an error.
+    /// With `mode`, the permissions of the socket file are set to it.
+    #[cfg(unix)]
+    pub(crate) fn bind_uds(path: &std::path::Path, mode: Option<u32>, backlog: i32) -> io::Result<Self> {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+        use std::os::unix::net::UnixStream;
+
+        if let Ok(meta) = std::fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is in use by a running server", path.display()),
+                    ));
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let socket = Socket::new(Domain::UNIX, Type::STREAM, None)?;
+        socket.bind(&socket2::SockAddr::unix(path)?)?;
+        if let Some(mode) = mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        socket.listen(backlog)?;
+        Ok(Listener::Uds(socket.into(), path.to_path_buf()))
+    }
+
+    /// The TCP address this listener is bound to, if it is a TCP listener.
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Listener::Tcp(l) => l.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Uds(..) => None,
+        }
+    }
+
+    /// The socket file to remove once the server has stopped, if any.
+    pub(crate) fn socket_path(&self) -> Option<&std::path::Path> {
+        match self {
+            Listener::Tcp(_) => None,
+            #[cfg(unix)]
+            Listener::Uds(_, path) => Some(path),
+        }
+    }
+
+    /// Creates another handle to the same socket, for another worker.
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Listener::Tcp(l) => Listener::Tcp(l.try_clone()?),
+            #[cfg(unix)]
+            Listener::Uds(l, path) => Listener::Uds(l.try_clone()?, path.clone()),
+        })
+    }
+}
+
+/// A listener registered with the worker's runtime.
+pub(crate) trait Accept {
+    type Stream: IoStream + 'static;
+
+    async fn accept(&self) -> io::Result<Self::Stream>;
+}
+
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<TcpStream> {
+        TcpListener::accept(self).await.map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(unix)]
+impl Accept for tokio::net::UnixListener {
+    type Stream = tokio::net::UnixStream;
+
+    async fn accept(&self) -> io::Result<tokio::net::UnixStream> {
+        tokio::net::UnixListener::accept(self).await.map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+    use super::*;
+
+    #[test]
+    fn replaces_stale_sockets_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("loony.sock");
+
+        // A socket file nobody listens on any more is taken over.
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let listener = Listener::bind_uds(&path, None, 16).unwrap();
+        assert_eq!(listener.socket_path(), Some(path.as_path()));
+
+        // One that is still being listened on is left alone.
+        let err = Listener::bind_uds(&path, None, 16).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+
+        let file = dir.path().join("not-a-socket");
+        std::fs::write(&file, "data").unwrap();
+        let err = Listener::bind_uds(&file, None, 16).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "data");
+    }
+
+    #[test]
+    fn applies_the_permission_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("loony.sock");
+        let _listener = Listener::bind_uds(&path, Some(0o660), 16).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+    }
+}
@@ -0,0 +1,347 @@
+//! `multipart/form-data` bodies, read one field at a time.
+
+use std::future::{Future, Ready, ready};
+use std::io::{self, Cursor, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, ready as fut_ready};
+use tempfile::NamedTempFile;
+use tokio::task::JoinHandle;
+
+use crate::error::HandlerError;
+use crate::extract::{FromRequest, check_content_type, check_limit};
+use crate::service::ServiceRequest;
+
+type Parser = ::multipart::server::Multipart<Cursor<Bytes>>;
+
+/// A field read on the blocking pool, along with the parser it was read from.
+type ReadField = JoinHandle<(Parser, Result<Option<Field>, HandlerError>)>;
+
+/// Settings for the `Multipart` extractor, registered with `App::app_data`.
+///
+/// The connection reads a request body whole before it is extracted, so
+/// uploads are also bounded by `ServerConfig::max_request_size`; raise both
+/// to accept larger ones.
+#[derive(Debug, Clone)]
+pub struct MultipartConfig {
+    field_limit: usize,
+    file_limit: usize,
+    total_limit: usize,
+}
+
+impl MultipartConfig {
+    /// The largest field without a filename accepted, in bytes. Such fields
+    /// are kept in memory. Defaults to 1 MiB.
+    pub fn field_limit(mut self, limit: usize) -> Self {
+        self.field_limit = limit;
+        self
+    }
+
+    /// The largest file accepted, in bytes. Defaults to 4 MiB, the default
+    /// `max_request_size`.
+    pub fn file_limit(mut self, limit: usize) -> Self {
+        self.file_limit = limit;
+        self
+    }
+
+    /// The largest body accepted, in bytes. Defaults to 4 MiB, the default
+    /// `max_request_size`.
+    pub fn total_limit(mut self, limit: usize) -> Self {
+        self.total_limit = limit;
+        self
+    }
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        MultipartConfig {
+            field_limit: 1024 * 1024,
+            file_limit: 4 * 1024 * 1024,
+            total_limit: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// A `multipart/form-data` body, yielding its fields in order, either as a
+/// `Stream` or through `next_field`.
+///
+/// Fields are read on the blocking thread pool, so the worker is never held
+/// up by disk I/O. A field with a filename is copied to a temp file a chunk
+/// at a time; other fields are kept in memory. Answers 415 for another
+/// content type, 413 when the body or a field is over the `MultipartConfig`
+/// limits and 400 for a malformed body.
+pub struct Multipart {
+    /// The parser, while no field is being read.
+    parser: Option<Parser>,
+    config: MultipartConfig,
+    done: bool,
+    /// The field being read, which hands the parser back once done.
+    reading: Option<ReadField>,
+}
+
+impl Multipart {
+    /// Reads the next field, or `None` after the last one. Nothing more is
+    /// read after an error.
+    pub async fn next_field(&mut self) -> Result<Option<Field>, HandlerError> {
+        self.next().await.transpose()
+    }
+}
+
+/// Reads the next field out of `parser`. Blocks on disk I/O for a file.
+fn read_field(parser: &mut Parser, config: &MultipartConfig) -> Result<Option<Field>, HandlerError> {
+    let mut entry = match parser.read_entry().map_err(malformed)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let name = entry.headers.name.to_string();
+    let limit = if entry.headers.filename.is_some() { config.file_limit } else { config.field_limit };
+    // One byte past the limit is enough to know the field is too large.
+    let mut reader = (&mut entry.data).take(limit as u64 + 1);
+
+    let (data, size) = if entry.headers.filename.is_some() {
+        let mut file = NamedTempFile::new().map_err(HandlerError::internal_error)?;
+        let mut buf = [0; 8 * 1024];
+        let mut size = 0;
+        loop {
+            let n = reader.read(&mut buf).map_err(malformed)?;
+            if n == 0 {
+                break;
+            }
+            size += n;
+            if size > limit {
+                break;
+            }
+            file.write_all(&buf[..n]).map_err(HandlerError::internal_error)?;
+        }
+        (FieldData::File(file), size)
+    } else {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).map_err(malformed)?;
+        let size = buf.len();
+        (FieldData::Memory(Bytes::from(buf)), size)
+    };
+
+    if size > limit {
+        return Err(HandlerError::FieldTooLarge { name, limit });
+    }
+    Ok(Some(Field {
+        name,
+        filename: entry.headers.filename.clone(),
+        content_type: entry.headers.content_type.as_ref().map(|mime| mime.to_string()),
+        size,
+        data,
+    }))
+}
+
+impl Stream for Multipart {
+    type Item = Result<Field, HandlerError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.reading.is_none() {
+            let Some(mut parser) = this.parser.take().filter(|_| !this.done) else {
+                return Poll::Ready(None);
+            };
+            let config = this.config.clone();
+            this.reading = Some(tokio::task::spawn_blocking(move || {
+                let field = read_field(&mut parser, &config);
+                (parser, field)
+            }));
+        }
+
+        let reading = this.reading.as_mut().expect("a field is being read");
+        let read = fut_ready!(Pin::new(reading).poll(cx));
+        this.reading = None;
+        let field = match read {
+            Ok((parser, field)) => {
+                this.parser = Some(parser);
+                field
+            }
+            Err(e) => Err(HandlerError::internal_error(e)),
+        };
+        if !matches!(field, Ok(Some(_))) {
+            this.done = true;
+        }
+        Poll::Ready(field.transpose())
+    }
+}
+
+impl FromRequest for Multipart {
+    type Future = Ready<Result<Multipart, HandlerError>>;
+
+    fn from_request(req: &ServiceRequest) -> Self::Future {
+        let config = req.extensions.get::<MultipartConfig>().cloned().unwrap_or_default();
+        let multipart = check_content_type(req, |mime| mime == "multipart/form-data")
+            .and_then(|_| check_limit(req, config.total_limit))
+            .and_then(|_| {
+                let boundary = req.req.header("Content-Type")
+                    .and_then(boundary)
+                    .ok_or_else(|| HandlerError::InvalidMultipart {
+                        message: "missing boundary".to_string(),
+                    })?;
+                Ok(Multipart {
+                    parser: Some(Parser::with_body(Cursor::new(req.req.body.clone()), boundary)),
+                    config,
+                    done: false,
+                    reading: None,
+                })
+            });
+        ready(multipart)
+    }
+}
+
+/// One field of a `Multipart` body.
+///
+/// The name, filename and content type are whatever the client sent, and
+/// should not be trusted as is.
+pub struct Field {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    size: usize,
+    data: FieldData,
+}
+
+enum FieldData {
+    Memory(Bytes),
+    File(NamedTempFile),
+}
+
+impl Field {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The length of the field's data, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the field was written to a temp file.
+    pub fn is_file(&self) -> bool {
+        matches!(self.data, FieldData::File(_))
+    }
+
+    /// The value of a field kept in memory, failing with 400 for a file or
+    /// a value that is not UTF-8.
+    pub fn text(&self) -> Result<&str, HandlerError> {
+        let invalid = |reason: &str| HandlerError::InvalidMultipart {
+            message: format!("field `{}` {}", self.name, reason),
+        };
+        match &self.data {
+            FieldData::Memory(bytes) => std::str::from_utf8(bytes).map_err(|_| invalid("is not UTF-8")),
+            FieldData::File(_) => Err(invalid("is a file")),
+        }
+    }
+
+    /// The data of a field kept in memory.
+    pub fn bytes(&self) -> Option<&Bytes> {
+        match &self.data {
+            FieldData::Memory(bytes) => Some(bytes),
+            FieldData::File(_) => None,
+        }
+    }
+
+    /// The temp file holding a file field's data. It is removed when
+    /// dropped, unless persisted with `NamedTempFile::persist`.
+    pub fn into_file(self) -> Option<NamedTempFile> {
+        match self.data {
+            FieldData::File(file) => Some(file),
+            FieldData::Memory(_) => None,
+        }
+    }
+}
+
+fn malformed(err: io::Error) -> HandlerError {
+    HandlerError::InvalidMultipart {
+        message: err.to_string(),
+    }
+}
+
+/// The `boundary` parameter of a `multipart/form-data` content type.
+fn boundary(content_type: &str) -> Option<&str> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ResponseError;
+    use crate::extensions::Extensions;
+    use crate::request::HttpRequest;
+    use crate::response::StatusCode;
+    use std::rc::Rc;
+    use super::*;
+
+    const BODY: &[u8] = b"--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        hello\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"avatar\"; filename=\"a.png\"\r\n\
+        Content-Type: image/png\r\n\r\n\
+        \x89PNG\x00\xff\r\n\
+        --XyZ--\r\n";
+
+    fn request(config: MultipartConfig) -> ServiceRequest {
+        let mut req = HttpRequest::new();
+        req.headers.push(("Content-Type".to_string(), "multipart/form-data; boundary=\"XyZ\"".to_string()));
+        req.body = Bytes::from_static(BODY);
+        let mut extensions = Extensions::new();
+        extensions.insert(config);
+        ServiceRequest { req, extensions: Rc::new(extensions) }
+    }
+
+    #[tokio::test]
+    async fn buffers_text_and_spills_files() {
+        let mut multipart = Multipart::from_request(&request(MultipartConfig::default())).await.ok().unwrap();
+
+        let title = multipart.next_field().await.ok().flatten().unwrap();
+        assert_eq!((title.name(), title.text().ok()), ("title", Some("hello")));
+
+        let avatar = multipart.next_field().await.ok().flatten().unwrap();
+        assert_eq!((avatar.filename(), avatar.content_type()), (Some("a.png"), Some("image/png")));
+        assert!(avatar.text().is_err());
+        let file = avatar.into_file().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"\x89PNG\x00\xff");
+
+        assert!(multipart.next_field().await.ok().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn enforces_limits() {
+        let config = MultipartConfig::default().field_limit(4);
+        let mut multipart = Multipart::from_request(&request(config)).await.ok().unwrap();
+        let err = multipart.next_field().await.err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::PayloadTooLarge);
+        assert!(multipart.next_field().await.ok().unwrap().is_none());
+
+        // Files have their own limit.
+        let config = MultipartConfig::default().field_limit(5).file_limit(5);
+        let mut multipart = Multipart::from_request(&request(config)).await.ok().unwrap();
+        assert!(multipart.next_field().await.ok().flatten().is_some());
+        let err = multipart.next_field().await.err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::PayloadTooLarge);
+        let config = MultipartConfig::default().field_limit(5).file_limit(6);
+        let mut multipart = Multipart::from_request(&request(config)).await.ok().unwrap();
+        assert!(multipart.next_field().await.ok().flatten().is_some());
+        assert!(multipart.next_field().await.ok().flatten().unwrap().is_file());
+
+        let err = Multipart::from_request(&request(MultipartConfig::default().total_limit(16))).await.err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::PayloadTooLarge);
+    }
+}
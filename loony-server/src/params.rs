@@ -0,0 +1,440 @@
+//! Serde deserializer over named string params, such as the ones captured
+//! from the path by the router.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+
+/// Why params could not be deserialized into the requested type.
+#[derive(Debug, thiserror::Error)]
+pub enum ParamsError {
+    #[error("invalid value {value:?} for `{name}`: {reason}")]
+    Value {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl de::Error for ParamsError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        ParamsError::Custom(msg.to_string())
+    }
+}
+
+/// Deserializes `params` into `T`.
+///
+/// A struct or map is filled in by param name, with the values of a name
+/// given more than once going into a sequence. A tuple or sequence takes the
+/// values in order, and a scalar needs exactly one param.
+pub fn from_params<'de, T: de::Deserialize<'de>>(params: &'de [(String, String)]) -> Result<T, ParamsError> {
+    T::deserialize(ParamsDeserializer { params })
+}
+
+/// Decodes `%XX` escapes, and `+` to a space if `plus_as_space`. Fails with
+/// the reason for an escape that is not `%` and two hex digits, or a result
+/// that is not UTF-8.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> Result<Cow<'_, str>, &'static str> {
+    if !(input.contains('%') || plus_as_space && input.contains('+')) {
+        return Ok(Cow::Borrowed(input));
+    }
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => match (bytes.get(i + 1).and_then(hex_value), bytes.get(i + 2).and_then(hex_value)) {
+                (Some(high), Some(low)) => {
+                    decoded.push(high << 4 | low);
+                    i += 2;
+                }
+                _ => return Err("invalid percent escape"),
+            },
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map(Cow::Owned).map_err(|_| "not UTF-8 once decoded")
+}
+
+fn hex_value(digit: &u8) -> Option<u8> {
+    (*digit as char).to_digit(16).map(|value| value as u8)
+}
+
+struct ParamsDeserializer<'de> {
+    params: &'de [(String, String)],
+}
+
+impl<'de> ParamsDeserializer<'de> {
+    fn single(&self) -> Result<ValueDeserializer<'de>, ParamsError> {
+        match self.params {
+            [(name, value)] => Ok(ValueDeserializer { name, value }),
+            params => Err(ParamsError::Custom(format!("expected 1 param, found {}", params.len()))),
+        }
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ParamsDeserializer<'de> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ParamsAccess::new(self.params))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let values = self.params.iter().map(|(name, value)| ValueDeserializer { name, value });
+        visitor.visit_seq(de::value::SeqDeserializer::new(values))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.params.len() != len {
+            return Err(ParamsError::Custom(format!("expected {} params, found {}", len, self.params.len())));
+        }
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_option deserialize_unit
+        deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// Walks the params as a map of name to values.
+///
+/// Every name is visited once, in the order it first appears, with the
+/// values of all params of that name, so repeated names can fill a sequence.
+struct ParamsAccess<'de> {
+    groups: std::vec::IntoIter<(&'de str, Vec<&'de str>)>,
+    value: Option<ValuesDeserializer<'de>>,
+}
+
+impl<'de> ParamsAccess<'de> {
+    fn new(params: &'de [(String, String)]) -> Self {
+        let mut index = HashMap::new();
+        let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+        for (name, value) in params {
+            let group = *index.entry(name.as_str()).or_insert_with(|| {
+                groups.push((name, Vec::new()));
+                groups.len() - 1
+            });
+            groups[group].1.push(value);
+        }
+        ParamsAccess { groups: groups.into_iter(), value: None }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for ParamsAccess<'de> {
+    type Error = ParamsError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match self.groups.next() {
+            Some((name, values)) => {
+                self.value = Some(ValuesDeserializer { name, values });
+                seed.deserialize(name.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let values = self.value.take().ok_or_else(|| ParamsError::Custom("value without a key".to_string()))?;
+        seed.deserialize(values)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.groups.len())
+    }
+}
+
+/// Deserializes all the values given for one name: a sequence takes every
+/// one of them, anything else needs exactly one.
+struct ValuesDeserializer<'de> {
+    name: &'de str,
+    values: Vec<&'de str>,
+}
+
+impl<'de> ValuesDeserializer<'de> {
+    fn single(&self) -> Result<ValueDeserializer<'de>, ParamsError> {
+        match self.values[..] {
+            [value] => Ok(ValueDeserializer { name: self.name, value }),
+            _ => Err(ParamsError::Custom(format!(
+                "expected a single value for `{}`, found {}", self.name, self.values.len()
+            ))),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValuesDeserializer<'de> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_any(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let name = self.name;
+        let values = self.values.into_iter().map(|value| ValueDeserializer { name, value });
+        visitor.visit_seq(de::value::SeqDeserializer::new(values))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_unit_struct(name, visitor)
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_map
+        deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// Deserializes a single param value, parsing it into whatever type is asked
+/// for.
+struct ValueDeserializer<'de> {
+    name: &'de str,
+    value: &'de str,
+}
+
+impl ValueDeserializer<'_> {
+    fn invalid(&self, reason: impl ToString) -> ParamsError {
+        ParamsError::Value {
+            name: self.name.to_string(),
+            value: self.value.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.value.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(e) => Err(self.invalid(e)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> IntoDeserializer<'de, ParamsError> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.value)
+    }
+
+    parse_value! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let name = self.name;
+        let value = self.value;
+        let variant = de::value::BorrowedStrDeserializer::<ParamsError>::new(value);
+        visitor.visit_enum(variant).map_err(|e| ValueDeserializer { name, value }.invalid(e))
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct UserPath {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn deserializes_scalars_tuples_and_structs() {
+        let p = params(&[("name", "loony"), ("id", "42")]);
+        assert_eq!(from_params::<UserPath>(&p).unwrap(), UserPath { id: 42, name: "loony".to_string() });
+        assert_eq!(from_params::<(String, u8)>(&p).unwrap(), ("loony".to_string(), 42));
+        assert_eq!(from_params::<u64>(&params(&[("id", "7")])).unwrap(), 7);
+        assert!(from_params::<u64>(&p).is_err());
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Search {
+        q: String,
+        tag: Vec<String>,
+        page: Option<u32>,
+    }
+
+    #[test]
+    fn collects_repeated_names() {
+        let p = params(&[("tag", "a"), ("q", "rust"), ("tag", "b")]);
+        let search = from_params::<Search>(&p).unwrap();
+        assert_eq!(search, Search { q: "rust".to_string(), tag: vec!["a".to_string(), "b".to_string()], page: None });
+        assert!(from_params::<Search>(&params(&[("q", "a"), ("q", "b"), ("tag", "x")])).is_err());
+    }
+
+    #[test]
+    fn groups_many_names_quickly() {
+        let p: Vec<_> = (0..100_000).map(|i| (format!("k{}", i), i.to_string())).collect();
+        let map = from_params::<HashMap<String, String>>(&p).unwrap();
+        assert_eq!(map.len(), 100_000);
+        assert_eq!(map["k99999"], "99999");
+    }
+
+    #[test]
+    fn names_the_failing_param() {
+        let p = params(&[("id", "abc")]);
+        match from_params::<(i32,)>(&p).unwrap_err() {
+            ParamsError::Value { name, value, .. } => assert_eq!((name.as_str(), value.as_str()), ("id", "abc")),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("John%20Doe+Jr", false).unwrap(), "John Doe+Jr");
+        assert_eq!(percent_decode("John%20Doe+Jr", true).unwrap(), "John Doe Jr");
+        assert_eq!(percent_decode("%E2%9C%93", false).unwrap(), "✓");
+        for broken in ["100%", "%zz", "%+5", "%-1", "%ff"] {
+            assert!(percent_decode(broken, false).is_err(), "{}", broken);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! `application/x-www-form-urlencoded` parsing, used for query strings and
+//! form bodies.
+
+use crate::error::ParseError;
+use crate::params::percent_decode;
+
+/// The decoded name/value pairs of a query string, in the order they were
+/// given. A name may appear more than once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryMap {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryMap {
+    /// Parses `input` (without the leading `?`). `+` decodes to a space and
+    /// `%XX` escapes to their byte; a broken escape or a value that is not
+    /// UTF-8 once decoded is an error. A pair without `=` has an empty value.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let pairs = input
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((decode(name)?, decode(value)?))
+            })
+            .collect::<Result<_, ParseError>>()?;
+        Ok(QueryMap { pairs })
+    }
+
+    /// The first value given for `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Every value given for `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs.iter().filter(move |(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.pairs.iter().any(|(n, _)| n == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// The pairs, as handed to `params::from_params`.
+    pub fn pairs(&self) -> &[(String, String)] {
+        &self.pairs
+    }
+}
+
+/// Decodes one urlencoded name or value.
+fn decode(input: &str) -> Result<String, ParseError> {
+    percent_decode(input, true)
+        .map(|decoded| decoded.into_owned())
+        .map_err(|reason| ParseError::InvalidQuery {
+            reason: format!("{} in {:?}", reason, input),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_pairs_in_order() {
+        let query = QueryMap::parse("tag=a&q=hello+big%20world&tag=b&flag&=x&&").unwrap();
+        assert_eq!(query.get("q"), Some("hello big world"));
+        assert_eq!(query.get_all("tag").collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get(""), Some("x"));
+        assert_eq!(query.len(), 5);
+    }
+
+    #[test]
+    fn rejects_broken_escapes() {
+        assert!(QueryMap::parse("q=100%").is_err());
+        assert!(QueryMap::parse("q=%zz").is_err());
+        assert!(QueryMap::parse("q=%ff").is_err());
+        assert!(QueryMap::parse("q=%+5").is_err());
+        assert_eq!(QueryMap::parse("q=%E2%9C%93").unwrap().get("q"), Some("✓"));
+    }
+}
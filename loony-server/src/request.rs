@@ -1,9 +1,14 @@
-use std::rc::Rc;
-
+use bytes::Bytes;
 use httparse::{Request, Status};
 
+use crate::error::{ParseError, ParseResult};
+use crate::query::QueryMap;
+
 pub const EMPTY_HEADER: Header<'static> = Header { name: "", value: b"" };
 
+/// The most headers a request may have.
+const MAX_HEADERS: usize = 64;
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Header<'a> {
     pub name: &'a str,
@@ -17,7 +22,9 @@ pub struct HttpRequest {
     pub uri: Option<String>,
     pub version: Option<u8>,
     pub headers: Vec<(String, String)>,
-    pub params: Rc<Vec<String>>,
+    /// Params captured by the router, in route template order.
+    pub path_params: Vec<(String, String)>,
+    pub body: Bytes,
 }
 
 impl HttpRequest {
@@ -27,13 +34,31 @@ impl HttpRequest {
             uri: None,
             version: None,
             headers: Vec::new(),
-            params: Rc::new(Vec::new())
+            path_params: Vec::new(),
+            body: Bytes::new(),
         }
     }
 
-    pub fn parse(&mut self, buffer: &[u8]) -> Result<usize, &'static str> {
+    /// Parses a complete request, copying everything after the head into
+    /// `body`. Returns the length of the head.
+    pub fn parse(&mut self, buffer: &[u8]) -> ParseResult<usize> {
+        let parsed_len = self.parse_head(buffer)?;
+        self.body = Bytes::copy_from_slice(&buffer[parsed_len..]);
+        Ok(parsed_len)
+    }
+
+    /// Like `parse`, but the body shares `buffer` instead of being copied.
+    pub fn parse_bytes(&mut self, buffer: Bytes) -> ParseResult<usize> {
+        let parsed_len = self.parse_head(&buffer)?;
+        self.body = buffer.slice(parsed_len..);
+        Ok(parsed_len)
+    }
+
+    /// Parses the request head alone, returning its length. Header values
+    /// that are not UTF-8 are kept with the bad bytes replaced.
+    pub(crate) fn parse_head(&mut self, buffer: &[u8]) -> ParseResult<usize> {
         // Create a headers array with a fixed size (common practice is 16-64)
-        let mut headers = [httparse::EMPTY_HEADER; 16];
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
         let mut req = Request::new(&mut headers);
             
         // Parse the request
@@ -46,9 +71,7 @@ impl HttpRequest {
                 
                 // Store URI
                 if let Some(path) = req.path {
-                    let (_, params) = parse_uri(&path);
                     self.uri = Some(path.to_string());
-                    self.params = Rc::new(params);
                 }
                 
                 // Store version
@@ -68,12 +91,65 @@ impl HttpRequest {
                 Ok(parsed_len)
             }
             Ok(Status::Partial) => {
-                Err("Incomplete HTTP request")
-            }
-            Err(e) => {
-                eprintln!("Parse error: {:?}", e);
-                Err("Failed to parse HTTP request")
+                Err(ParseError::malformed_headers("incomplete request head"))
             }
+            Err(e) => Err(parse_error(e, buffer)),
+        }
+    }
+}
+
+impl HttpRequest {
+    /// Returns the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the values of every header named `name`, ignoring case.
+    pub fn headers_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the path param captured as `:name` by the matched route.
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The part of the URI after `?`, or `""` without one.
+    pub fn query_string(&self) -> &str {
+        self.uri
+            .as_deref()
+            .and_then(|uri| uri.split_once('?'))
+            .map(|(_, query)| query.split('#').next().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// The decoded query string pairs.
+    pub fn query(&self) -> Result<QueryMap, ParseError> {
+        QueryMap::parse(self.query_string())
+    }
+
+    /// Whether the client wants the connection kept open after this request.
+    ///
+    /// HTTP/1.1 connections are persistent unless the client sends
+    /// `Connection: close`; HTTP/1.0 ones only with `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.header("Connection")
+                .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+                .unwrap_or(false)
+        };
+        match self.version {
+            Some(1) => !has_token("close"),
+            _ => has_token("keep-alive"),
         }
     }
 }
@@ -86,13 +162,67 @@ impl Default for HttpRequest {
 }
 
 
-/// Parses URI into path and query parameters
-fn parse_uri(uri: &str) -> (String, Vec<String>) {
-    let parts: Vec<&str> = uri.split('?').collect();
-    let path = parts.first().map(|&p| p.to_string()).unwrap_or_default();
-    let query_params = parts.get(1)
-        .map(|&q| q.split('&').map(String::from).collect())
-        .unwrap_or_default();
+/// Turns an `httparse` failure into the matching `ParseError`, naming the
+/// part of the request line at fault where there is one.
+fn parse_error(err: httparse::Error, buffer: &[u8]) -> ParseError {
+    let line_end = buffer.iter().position(|&b| b == b'\n').unwrap_or(buffer.len());
+    let line = String::from_utf8_lossy(&buffer[..line_end]);
+    let mut parts = line.trim_end().splitn(3, ' ');
+    let method = parts.next().unwrap_or_default();
+    let uri = parts.next().unwrap_or_default();
+    let version = parts.next().unwrap_or_default();
+    match err {
+        httparse::Error::Token | httparse::Error::Status if !is_token(method) => {
+            ParseError::InvalidMethod { method: method.to_string() }
+        }
+        httparse::Error::Token => ParseError::InvalidUri { uri: uri.to_string() },
+        httparse::Error::Version => ParseError::InvalidVersion { version: version.to_string() },
+        httparse::Error::TooManyHeaders => {
+            ParseError::malformed_headers(&format!("more than {} headers", MAX_HEADERS))
+        }
+        e => ParseError::malformed_headers(&e.to_string()),
+    }
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    (path, query_params)
+    fn parse(raw: &str) -> HttpRequest {
+        let mut req = HttpRequest::new();
+        req.parse(raw.as_bytes()).unwrap();
+        req
+    }
+
+    #[test]
+    fn keep_alive_defaults() {
+        assert!(parse("GET / HTTP/1.1\r\n\r\n").keep_alive());
+        assert!(!parse("GET / HTTP/1.1\r\nConnection: close\r\n\r\n").keep_alive());
+        assert!(!parse("GET / HTTP/1.0\r\n\r\n").keep_alive());
+        assert!(parse("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").keep_alive());
+    }
+
+    #[test]
+    fn reports_what_failed_to_parse() {
+        let err = |raw: &str| HttpRequest::new().parse(raw.as_bytes()).unwrap_err();
+        assert!(matches!(err("G(T / HTTP/1.1\r\n\r\n"), ParseError::InvalidMethod { method } if method == "G(T"));
+        assert!(matches!(err("GET / HTTP/2.5\r\n\r\n"), ParseError::InvalidVersion { .. }));
+        assert!(matches!(err("GET / HTTP/1.1\r\nBad Header: x\r\n\r\n"), ParseError::MalformedHeaders { .. }));
+        let many: String = (0..=MAX_HEADERS).map(|i| format!("X-{}: 1\r\n", i)).collect();
+        assert!(matches!(err(&format!("GET / HTTP/1.1\r\n{}\r\n", many)), ParseError::MalformedHeaders { .. }));
+    }
+
+    #[test]
+    fn body_follows_head() {
+        let mut req = HttpRequest::new();
+        let raw = Bytes::from_static(b"POST /users HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello");
+        let head_len = req.parse_bytes(raw).unwrap();
+        assert_eq!(head_len, 43);
+        assert_eq!(req.body, Bytes::from_static(b"hello"));
+        assert!(parse("GET / HTTP/1.1\r\n\r\n").body.is_empty());
+    }
 }
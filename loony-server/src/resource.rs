@@ -4,9 +4,11 @@ use std::{
 };
 
 use crate::{
+    error::Error,
   route::RouteServices, 
   route::{
     BoxedRouteService, 
+    Method,
     Route, 
     RouteFutureService
   }, service::{
@@ -14,7 +16,7 @@ use crate::{
     ServiceResponse,
     AppServiceFactory,
   }};
-use async_std::task::block_on;
+use futures::executor::block_on;
 use futures::{Future, FutureExt};
 use loony_service::{ServiceFactory, Service};
 
@@ -40,7 +42,7 @@ impl Resource {
 impl ServiceFactory for Resource {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
+    type Error = Error;
     type Service = FinalRouteService;
     type Future = FinalFutureRouteService;
     type InitError = ();
@@ -52,6 +54,7 @@ impl ServiceFactory for Resource {
         let fut = self.route.new_service(());
         FinalFutureRouteService {
           route_name,
+          methods: self.route.methods.clone(),
           fut,
         }
     }
@@ -70,18 +73,21 @@ pub struct FinalFutureRouteService {
     #[pin]
     pub fut: RouteFutureService,
     pub route_name: String,
+    pub methods: Vec<Method>,
 }
 
 pub struct FinalRouteService {
     pub service: BoxedRouteService,
     pub route_name: String,
+    /// The methods this service answers; empty means any method.
+    pub methods: Vec<Method>,
 }
 
 impl Service for FinalRouteService {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
-    type Future = Pin<Box<dyn Future<Output=Result<ServiceResponse, ()>>>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output=Result<ServiceResponse, Error>>>>;
 
     fn call(&mut self, req: Self::Request) -> Self::Future {
         self.service.call(req).boxed_local()
@@ -95,6 +101,7 @@ impl Future for FinalFutureRouteService {
           Poll::Ready(service) => Poll::Ready(Ok(FinalRouteService {
               service: service.unwrap(),
               route_name: self.route_name.clone(),
+              methods: self.methods.clone(),
           })),
           Poll::Pending => Poll::Pending
         }
@@ -110,7 +117,7 @@ mod tests {
     use crate::service::AppServiceFactory;
     use crate::service::ServiceRequest;
     use crate::resource::Resource;
-    use async_std::task::block_on;
+    use futures::executor::block_on;
     use loony_service::Service;
     use std::rc::Rc;
     use crate::route::RouteServices;
@@ -126,14 +133,16 @@ mod tests {
       let mut route_services = RouteServices::new();
       resource.register(&mut route_services);
 
-      let one = route_services.services.get(0).unwrap();
-      let req = HttpRequest::new();
+      let one = route_services.services.first().unwrap();
+      let mut req = HttpRequest::new();
+      req.uri = Some("/home".to_string());
       let ext = Extensions::new();
       let service_request = ServiceRequest { req, extensions:Rc::new(ext) };
 
       let res = one.borrow_mut().call(service_request);
       let res = block_on(res).unwrap();
       let res = res.0;
-      assert_eq!("Hello World!".to_string(), res);
+      assert_eq!(res.status_code(), crate::response::StatusCode::Ok);
+      assert_eq!(&res.body[..], b"Hello World!");
     }
 }
\ No newline at end of file
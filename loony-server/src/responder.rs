@@ -1,86 +1,86 @@
 use std::{collections::HashMap, future::{Future, Ready, ready}};
-use crate::{response::{HttpResponse, StatusCode}, service::ServiceResponse};
+use bytes::Bytes;
+use futures_util::Stream;
+use serde::Serialize;
+use futures_util::future::Either;
+use crate::{error::{BoxError, Error, HandlerError, ResponseError}, response::{HttpResponse, StatusCode}, service::ServiceResponse};
 
 pub trait Responder {
-    type Future: Future<Output=ServiceResponse>;
-    fn respond(&self) -> Self::Future;
+    type Future: Future<Output=Result<ServiceResponse, Error>>;
+    fn respond(self) -> Self::Future;
 }
 
 // Implement Responder for String
 impl Responder for String {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
-        let response = HttpResponse::new().body(self.clone()).build();
-        ready(ServiceResponse(response))
+    fn respond(self) -> Self::Future {
+        ready(Ok(ServiceResponse(HttpResponse::new().body(self))))
     }
 }
 
 // Implement Responder for &str (avoid cloning when possible)
 impl Responder for &str {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
-        let response = HttpResponse::new().body(self.to_string()).build();
-        ready(ServiceResponse(response))
+    fn respond(self) -> Self::Future {
+        ready(Ok(ServiceResponse(HttpResponse::new().body(Bytes::copy_from_slice(self.as_bytes())))))
     }
 }
 
-// Implement Responder for Result<String, E>
-impl<E> Responder for Result<String, E>
+// Implement Responder for Result<R, E>, so handlers can fail with any
+// ResponseError
+impl<R, E> Responder for Result<R, E>
 where
-    E: std::fmt::Display,
+    R: Responder,
+    E: ResponseError + 'static,
 {
-    type Future = Ready<ServiceResponse>;
+    type Future = Either<R::Future, Ready<Result<ServiceResponse, Error>>>;
 
-    fn respond(&self) -> Self::Future {
+    fn respond(self) -> Self::Future {
         match self {
-            Ok(success) => {
-                let response = HttpResponse::new().body(success.clone()).build();
-                ready(ServiceResponse(response))
-            }
-            Err(error) => {
-                let response = HttpResponse::new().body(error.to_string())
-                    .with_status(StatusCode::InternalServerError).build();
-                ready(ServiceResponse(response))
-            }
+            Ok(responder) => Either::Left(responder.respond()),
+            Err(error) => Either::Right(ready(Err(error.into()))),
         }
     }
 }
 
 // Implement Responder for HttpResponse
 impl Responder for HttpResponse {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
-        ready(ServiceResponse(self.clone().build()))
+    fn respond(self) -> Self::Future {
+        ready(Ok(ServiceResponse(self)))
     }
 }
 
 // Implement Responder for Vec<u8> (binary data)
 impl Responder for Vec<u8> {
-    type Future = Ready<ServiceResponse>;
-
-    fn respond(&self) -> Self::Future {
-        // Convert bytes to string (you might want to handle this differently)
-        let body = String::from_utf8_lossy(self).to_string();
-        let mut response = HttpResponse::new().body(body);
-        response.headers.insert("Content-Type".to_string(), "application/octet-stream".to_string());
-        let response = response.build();
-        ready(ServiceResponse(response))
+    type Future = Ready<Result<ServiceResponse, Error>>;
+
+    fn respond(self) -> Self::Future {
+        Bytes::from(self).respond()
     }
 }
 
 // Implement Responder for &[u8] (binary data slice)
 impl Responder for &[u8] {
-    type Future = Ready<ServiceResponse>;
-
-    fn respond(&self) -> Self::Future {
-        let body = String::from_utf8_lossy(self).to_string();
-        let mut response = HttpResponse::new().body(body);
-        response.headers.insert("Content-Type".to_string(), "application/octet-stream".to_string());
-        let response = response.build();
-        ready(ServiceResponse(response))
+    type Future = Ready<Result<ServiceResponse, Error>>;
+
+    fn respond(self) -> Self::Future {
+        Bytes::copy_from_slice(self).respond()
+    }
+}
+
+// Implement Responder for Bytes (binary data, sent as is)
+impl Responder for Bytes {
+    type Future = Ready<Result<ServiceResponse, Error>>;
+
+    fn respond(self) -> Self::Future {
+        let response = HttpResponse::new()
+            .content_type("application/octet-stream")
+            .body(self);
+        ready(Ok(ServiceResponse(response)))
     }
 }
 
@@ -91,7 +91,7 @@ impl Responder for &[u8] {
 // {
 //     type Future = Ready<ServiceResponse>;
 
-//     fn respond(&self) -> Self::Future {
+//     fn respond(self) -> Self::Future {
 //         match HttpResponse::with_json(self) {
 //             Ok(response) => ready(ServiceResponse(response)),
 //             Err(error) => {
@@ -126,15 +126,14 @@ impl Responder for &[u8] {
 impl<T> Responder for (StatusCode, T)
 where
     T: Into<String>,
-    for<'a> &'a T: Into<String>,
 {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
+    fn respond(self) -> Self::Future {
         let (status, body) = self;
-        let response = HttpResponse::with_body(body)
-            .with_status(*status).build();
-        ready(ServiceResponse(response))
+        let response = HttpResponse::with_body(body.into())
+            .with_status(status);
+        ready(Ok(ServiceResponse(response)))
     }
 }
 
@@ -142,27 +141,24 @@ where
 impl<T> Responder for (StatusCode, HashMap<String, String>, T)
 where
     T: Into<String>,
-    for<'a> &'a T: Into<String>,
 {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
+    fn respond(self) -> Self::Future {
         let (status, headers, body) = self;
-        let mut response = HttpResponse::with_body(body)
-            .with_status(*status);
-        response.headers.extend(headers.clone());
-        let response = response.build();
-        ready(ServiceResponse(response))
+        let mut response = HttpResponse::with_body(body.into())
+            .with_status(status);
+        response.headers.extend(headers);
+        ready(Ok(ServiceResponse(response)))
     }
 }
 
 // Implement Responder for () - empty response
 impl Responder for () {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
-        let response = HttpResponse::new().build();
-        ready(ServiceResponse(response))
+    fn respond(self) -> Self::Future {
+        ready(Ok(ServiceResponse(HttpResponse::new())))
     }
 }
 
@@ -170,15 +166,14 @@ impl Responder for () {
 pub struct Redirect(pub String);
 
 impl Responder for Redirect {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
+    fn respond(self) -> Self::Future {
         let mut response = HttpResponse::new()
             .with_status(StatusCode::Found)
             .with_header("Location", &self.0);
-        response.body = Some(format!("Redirecting to {}", self.0));
-        let response = response.build();
-        ready(ServiceResponse(response))
+        response.body = Bytes::from(format!("Redirecting to {}", self.0));
+        ready(Ok(ServiceResponse(response)))
     }
 }
 
@@ -187,14 +182,14 @@ pub struct Html<T>(pub T);
 
 impl<T> Responder for Html<T>
 where
-    T: Into<String> + Clone,
+    T: Into<String>,
 {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
-        let response = HttpResponse::with_body(self.0.clone())
-            .with_header("Content-Type", "text/html; charset=utf-8").build();
-        ready(ServiceResponse(response))
+    fn respond(self) -> Self::Future {
+        let response = HttpResponse::with_body(self.0.into())
+            .with_header("Content-Type", "text/html; charset=utf-8");
+        ready(Ok(ServiceResponse(response)))
     }
 }
 
@@ -203,13 +198,44 @@ pub struct Text<T>(pub T);
 
 impl<T> Responder for Text<T>
 where
-    T: Into<String> + Clone,
+    T: Into<String>,
 {
-    type Future = Ready<ServiceResponse>;
+    type Future = Ready<Result<ServiceResponse, Error>>;
 
-    fn respond(&self) -> Self::Future {
-        let response = HttpResponse::with_body(self.0.clone())
-            .with_header("Content-Type", "text/plain; charset=utf-8").build();
-        ready(ServiceResponse(response))
+    fn respond(self) -> Self::Future {
+        let response = HttpResponse::with_body(self.0.into())
+            .with_header("Content-Type", "text/plain; charset=utf-8");
+        ready(Ok(ServiceResponse(response)))
     }
-}
\ No newline at end of file
+}
+
+// Custom type for JSON bodies, extracted from requests and serialized into
+// responses
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> Responder for Json<T> {
+    type Future = Ready<Result<ServiceResponse, Error>>;
+
+    fn respond(self) -> Self::Future {
+        let response = serde_json::to_vec(&self.0)
+            .map(|body| ServiceResponse(HttpResponse::new().content_type("application/json").body(body)))
+            .map_err(|e| HandlerError::internal_error(e).into());
+        ready(response)
+    }
+}
+
+// Custom type for streamed responses, sent chunk by chunk as the stream
+// yields them
+pub struct Streaming<S>(pub S);
+
+impl<S, E> Responder for Streaming<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + 'static,
+    E: Into<BoxError> + 'static,
+{
+    type Future = Ready<Result<ServiceResponse, Error>>;
+
+    fn respond(self) -> Self::Future {
+        ready(Ok(ServiceResponse(HttpResponse::new().streaming(self.0))))
+    }
+}
@@ -5,10 +5,15 @@
 
 
 use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use std::pin::Pin;
 
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
 use serde::Serialize;
 
+use crate::error::BoxError;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum HttpVersion {
     Http1_0,
@@ -404,12 +409,41 @@ impl std::error::Error for InvalidStatusCode {}
 //     }
 // }
 
-#[derive(Debug, Clone)]
+/// A response body produced piece by piece, e.g. a large export.
+pub struct BodyStream(Pin<Box<dyn Stream<Item = Result<Bytes, BoxError>>>>);
+
+impl BodyStream {
+    pub fn new<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        BodyStream(Box::pin(stream.map(|chunk| chunk.map_err(Into::into))))
+    }
+
+    /// The next piece of the body, or `None` once it is complete.
+    pub async fn next(&mut self) -> Option<Result<Bytes, BoxError>> {
+        self.0.next().await
+    }
+}
+
+impl Debug for BodyStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BodyStream")
+    }
+}
+
+/// A response as handlers and middleware see it.
+///
+/// It stays structured until the connection writes it out, which is also
+/// where `Content-Length` and the other framing headers are filled in.
+#[derive(Debug)]
 pub struct HttpResponse {
     version: HttpVersion,
     status: StatusCode,
     pub headers: HashMap<String, String>,
-    pub body: Option<String>,
+    pub body: Bytes,
+    pub stream: Option<BodyStream>,
 }
 
 impl HttpResponse {
@@ -418,10 +452,19 @@ impl HttpResponse {
             version: HttpVersion::Http1_1,
             status: StatusCode::Ok,
             headers: HashMap::new(),
-            body: None,
+            body: Bytes::new(),
+            stream: None,
         }
     }
 
+    pub fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn http_version(&self) -> &HttpVersion {
+        &self.version
+    }
+
     pub fn version(mut self, version: HttpVersion) -> Self {
         self.version = version;
         self
@@ -451,31 +494,12 @@ impl HttpResponse {
         self
     }
 
-    pub fn with_body<T: Into<String>>(body: T) -> Self {
-        let body_str = body.into();
-        let mut headers = HashMap::new();
-        headers.insert("Content-Length".to_string(), body_str.len().to_string());
-        
-        Self {
-            status: StatusCode::Ok,
-            headers,
-            body: Some(body_str),
-            version: HttpVersion::Http1_1,
-        }
+    pub fn with_body<T: Into<Bytes>>(body: T) -> Self {
+        Self::new().body(body)
     }
 
     pub fn with_json<T: Serialize>(data: T) -> Result<Self, serde_json::Error> {
-        let body = serde_json::to_string(&data)?;
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
-        headers.insert("Content-Length".to_string(), body.len().to_string());
-
-        Ok(Self {
-            status: StatusCode::Ok,
-            headers,
-            body: Some(body),
-            version: HttpVersion::Http1_1,
-        })
+        Self::new().json(data)
     }
 
     pub fn content_type(mut self, content_type: &str) -> Self {
@@ -483,41 +507,34 @@ impl HttpResponse {
         self
     }
 
-    pub fn body<T: Into<String>>(mut self, body: T) -> Self {
-        let body_str = body.into();
-        self.headers.insert("Content-Length".to_string(), body_str.len().to_string());
-        self.body = Some(body_str);
+    pub fn body<T: Into<Bytes>>(mut self, body: T) -> Self {
+        self.body = body.into();
+        self.stream = None;
         self
     }
 
     pub fn json<T: serde::Serialize>(mut self, data: T) -> Result<Self, serde_json::Error> {
-        let json_string = serde_json::to_string(&data)?;
+        let json = serde_json::to_vec(&data)?;
         self.headers.insert("Content-Type".to_string(), "application/json".to_string());
-        self.headers.insert("Content-Length".to_string(), json_string.len().to_string());
-        self.body = Some(json_string);
-        Ok(self)
+        Ok(self.body(json))
     }
 
-    pub fn build(self) -> String {
-        let status_line = format!("{} {}", self.version, self.status);
-        
-        let headers: Vec<String> = self.headers
-            .iter()
-            .map(|(k, v)| format!("{}: {}", k, v))
-            .collect();
-        
-        let headers_section = if headers.is_empty() {
-            String::new()
-        } else {
-            format!("{}\r\n", headers.join("\r\n"))
-        };
-
-        let body_section = match self.body {
-            Some(body) => format!("\r\n{}", body),
-            None => String::new(),
-        };
+    /// Sends the body from `stream` as it produces it, instead of all at
+    /// once. The length is not known up front, so the body goes out chunked
+    /// to HTTP/1.1 clients, and HTTP/1.0 ones read it until the connection
+    /// closes.
+    pub fn streaming<S, E>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        self.body = Bytes::new();
+        self.stream = Some(BodyStream::new(stream));
+        self
+    }
 
-        format!("{}\r\n{}{}", status_line, headers_section, body_section)
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
     }
 }
 
@@ -540,10 +557,27 @@ impl HttpResponse {
     pub fn no_content() -> Self {
         Self::new()
             .status(StatusCode::NoContent)
-            .header("Content-Length", "0")
     }
 
     // Error responses
+    /// Replaces the body with an RFC 7807 problem details object for the
+    /// response's status, keeping its other headers.
+    pub fn problem(self, detail: Option<&str>, instance: Option<&str>) -> Self {
+        let status = self.status;
+        let mut problem = serde_json::json!({
+            "type": "about:blank",
+            "title": status.reason_phrase(),
+            "status": status.as_u16(),
+        });
+        if let Some(detail) = detail {
+            problem["detail"] = detail.into();
+        }
+        if let Some(instance) = instance {
+            problem["instance"] = instance.into();
+        }
+        self.content_type("application/problem+json").body(problem.to_string())
+    }
+
     pub fn bad_request() -> Self {
         Self::new().status(StatusCode::BadRequest)
     }
@@ -558,11 +592,11 @@ impl HttpResponse {
 
     // Common content types
     pub fn html<T: Into<String>>(self, content: T) -> Self {
-        self.content_type("text/html; charset=utf-8").body(content)
+        self.content_type("text/html; charset=utf-8").body(content.into())
     }
 
     pub fn text<T: Into<String>>(self, content: T) -> Self {
-        self.content_type("text/plain; charset=utf-8").body(content)
+        self.content_type("text/plain; charset=utf-8").body(content.into())
     }
 
     pub fn json_body<T: serde::Serialize>(self, data: T) -> Result<Self, serde_json::Error> {
@@ -1,29 +1,79 @@
 use std::{
     cell::RefCell, future::Future, pin::Pin, rc::Rc, task::{Context, Poll}
 };
-use async_std::task::block_on;
+use futures::executor::block_on;
 use loony_service::{
     Service,
     ServiceFactory
 };
 use crate::{
+    error::Error,
     extract::{Extract, FromRequest}, 
     handler::{Factory, Handler}, 
     resource::{FinalRouteService}, responder::Responder, scope::Scope, service::{AppServiceFactory, ServiceRequest, ServiceResponse}
 };
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Method {
   GET,
   POST,
+  PUT,
+  PATCH,
+  DELETE,
+  HEAD,
+  OPTIONS,
+  TRACE,
+  CONNECT,
+  /// An extension method, e.g. `PROPFIND`. Methods are case-sensitive.
+  Custom(String),
+}
+
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::GET => "GET",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::PATCH => "PATCH",
+            Method::DELETE => "DELETE",
+            Method::HEAD => "HEAD",
+            Method::OPTIONS => "OPTIONS",
+            Method::TRACE => "TRACE",
+            Method::CONNECT => "CONNECT",
+            Method::Custom(method) => method,
+        }
+    }
+}
+
+impl From<&str> for Method {
+    fn from(method: &str) -> Self {
+        match method {
+            "GET" => Method::GET,
+            "POST" => Method::POST,
+            "PUT" => Method::PUT,
+            "PATCH" => Method::PATCH,
+            "DELETE" => Method::DELETE,
+            "HEAD" => Method::HEAD,
+            "OPTIONS" => Method::OPTIONS,
+            "TRACE" => Method::TRACE,
+            "CONNECT" => Method::CONNECT,
+            other => Method::Custom(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 pub type BoxedRouteService = Box<
     dyn Service<
         Request=ServiceRequest,
         Response=ServiceResponse,
-        Error=(),
-        Future=Pin<Box<dyn Future<Output=Result<ServiceResponse, ()>>>>
+        Error = Error,
+        Future=Pin<Box<dyn Future<Output=Result<ServiceResponse, Error>>>>
     >
 >;
 
@@ -31,7 +81,7 @@ pub type BoxedRouteServiceFactory = Box<
     dyn ServiceFactory<
         Request=ServiceRequest,
         Response=ServiceResponse,
-        Error=(),
+        Error = Error,
         Service=BoxedRouteService,
         Future=Pin<Box<dyn Future<Output=Result<BoxedRouteService, ()>>>>,
         Config=(),
@@ -50,10 +100,11 @@ pub type BoxService = Pin<
 pub struct Route {
     pub path: String,
     pub service: BoxedRouteServiceFactory,
-    pub method: Method,
+    /// The methods this route answers; empty means any method.
+    pub methods: Vec<Method>,
 }
 
-impl<'route> Route {
+impl Route {
     pub fn new(path: &str) -> Route {
         Route {
             path: path.to_owned(),
@@ -64,7 +115,7 @@ impl<'route> Route {
                     )
                 )
             ),
-            method: Method::GET,
+            methods: vec![Method::GET],
         }
     }
 
@@ -81,8 +132,15 @@ impl<'route> Route {
         self
     }
 
+    /// Answers `method` requests only.
     pub fn method(mut self, method: Method) -> Self {
-        self.method = method;
+        self.methods = vec![method];
+        self
+    }
+
+    /// Answers requests with any of `methods`, e.g. both `PUT` and `PATCH`.
+    pub fn methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
         self
     }
 }
@@ -91,7 +149,11 @@ impl AppServiceFactory for Route {
     fn register(&mut self, config: &mut RouteServices) {
         let service = self.service.new_service(());
         let service = block_on(service).unwrap();
-        config.service(FinalRouteService { service, route_name: self.path.clone() });
+        config.service(FinalRouteService {
+            service,
+            route_name: self.path.clone(),
+            methods: self.methods.clone(),
+        });
     }
 }
 
@@ -103,6 +165,12 @@ pub struct RouteServices {
   pub services: Vec<Rc<RefCell<FinalRouteService>>>
 }
 
+impl Default for RouteServices {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl RouteServices {
   pub fn new() -> Self {
     RouteServices {
@@ -122,8 +190,8 @@ impl RouteServices {
 impl Service for RouteService {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
-    type Future = Pin<Box<dyn Future<Output=Result<Self::Response, ()>>>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Self::Error>>>>;
 
     fn call(&mut self, req: ServiceRequest) -> Self::Future {
         self.service.call(req)
@@ -133,7 +201,7 @@ impl Service for RouteService {
 impl ServiceFactory for Route {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
+    type Error = Error;
     type Service = RouteService;
     type InitError = ();
     type Config = ();
@@ -190,7 +258,7 @@ where
         Config = (),
         Request = ServiceRequest,
         Response = ServiceResponse,
-        Error = (),
+        Error = Error,
         InitError = ()
     >,
     T::Future: 'static,
@@ -200,7 +268,7 @@ where
     type Request = ServiceRequest;
     type Response = ServiceResponse;
     type Config = ();
-    type Error = ();
+    type Error = Error;
     type InitError = ();
     type Service = BoxedRouteService;
     type Future = Pin<Box<dyn Future<Output=Result<Self::Service, ()>>>>;
@@ -223,7 +291,7 @@ where
     S: Service<
         Request = ServiceRequest,
         Response = ServiceResponse,
-        Error = (),
+        Error = Error,
     > + 'static,
     <S as Service>::Future: 'static,
 {
@@ -258,13 +326,13 @@ where
     T: Service<
         Request = ServiceRequest,
         Response = ServiceResponse,
-        Error = (),
+        Error = Error,
     >,
 {
     type Request = ServiceRequest;
     type Response = ServiceResponse;
-    type Error = ();
-    type Future = Pin<Box<dyn Future<Output=Result<ServiceResponse, ()>>>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output=Result<ServiceResponse, Error>>>>;
 
     fn call(&mut self, req: Self::Request) -> Self::Future {
         let fut = self.factory.call(req);
@@ -280,9 +348,9 @@ struct CallFut<F> {
 
 impl<F> Future for CallFut<F>
 where
-    F: Future<Output = Result<ServiceResponse, ()>>,
+    F: Future<Output = Result<ServiceResponse, Error>>,
 {
-    type Output = Result<ServiceResponse, ()>;
+    type Output = Result<ServiceResponse, Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.project().inner.poll(cx)? {
@@ -304,6 +372,32 @@ pub fn post(path: &str) -> Route {
     method(path, Method::POST)
 }
 
+pub fn put(path: &str) -> Route {
+    method(path, Method::PUT)
+}
+
+pub fn patch(path: &str) -> Route {
+    method(path, Method::PATCH)
+}
+
+pub fn delete(path: &str) -> Route {
+    method(path, Method::DELETE)
+}
+
+pub fn head(path: &str) -> Route {
+    method(path, Method::HEAD)
+}
+
+pub fn options(path: &str) -> Route {
+    method(path, Method::OPTIONS)
+}
+
+/// A route that answers every method not claimed by another route on the
+/// same path.
+pub fn any(path: &str) -> Route {
+    Route::new(path).methods(&[])
+}
+
 pub fn scope(scope: &str) -> Scope {
   Scope::new(scope)
 }
@@ -331,7 +425,8 @@ mod tests {
         let mut b = block_on(a).unwrap();
 
         let ext = Extensions::new();
-        let req = HttpRequest::new();
+        let mut req = HttpRequest::new();
+        req.uri = Some("/home".to_string());
         let sr = ServiceRequest {
             req,
             extensions: Rc::new(ext),
@@ -340,6 +435,32 @@ mod tests {
         let c = b.call(sr);
         let d = block_on(c).unwrap();
         let e = d.0;
-        assert_eq!("Hello World!".to_string(), e);
+        assert_eq!(e.status_code(), crate::response::StatusCode::Ok);
+        assert_eq!(&e.body[..], b"Hello World!");
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("teapot")]
+    struct Teapot;
+
+    impl crate::error::ResponseError for Teapot {
+        fn status_code(&self) -> crate::response::StatusCode {
+            crate::response::StatusCode::ImATeapot
+        }
+    }
+
+    async fn brew(_: String) -> Result<String, Teapot> {
+        Err(Teapot)
+    }
+
+    #[test]
+    fn handler_errors_carry_their_response() {
+        let mut service = block_on(Route::new("/brew").to(brew).new_service(())).unwrap();
+        let mut req = HttpRequest::new();
+        req.uri = Some("/brew".to_string());
+        let err = block_on(service.call(ServiceRequest { req, extensions: Rc::new(Extensions::new()) })).err().unwrap();
+        let response = err.error_response();
+        assert_eq!(response.status_code(), crate::response::StatusCode::ImATeapot);
+        assert_eq!(&response.body[..], b"teapot");
     }
 }
\ No newline at end of file
@@ -1,12 +1,63 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use loony_router::radix::RadixRouter;
 
-use crate::{resource::FinalRouteService, route::Route, service::{AppServiceFactory, HttpServiceFactory, ServiceFactoryWrapper}};
+use crate::{resource::FinalRouteService, route::{Method, Route}, service::{AppServiceFactory, HttpServiceFactory, ServiceFactoryWrapper}};
+
+/// The services registered for one path: one per method, plus one that
+/// answers any other method.
+#[derive(Default)]
+struct MethodServices {
+    methods: Vec<(Method, Rc<RefCell<FinalRouteService>>)>,
+    any: Option<Rc<RefCell<FinalRouteService>>>,
+    // `:param` names of the route template, in order
+    params: Vec<String>,
+}
+
+impl MethodServices {
+    fn get(&self, method: &str) -> Option<&Rc<RefCell<FinalRouteService>>> {
+        self.methods.iter().find(|(m, _)| m.as_str() == method).map(|(_, service)| service)
+    }
+
+    /// The methods this path answers, including the `HEAD` and `OPTIONS`
+    /// the server answers for it.
+    fn allowed(&self) -> Vec<Method> {
+        let mut allowed: Vec<Method> = self.methods.iter().map(|(m, _)| m.clone()).collect();
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+        if !allowed.contains(&Method::OPTIONS) {
+            allowed.push(Method::OPTIONS);
+        }
+        allowed
+    }
+}
+
+/// The outcome of looking up a request in `AllRouteServices`.
+pub enum RouteMatch {
+    /// The service to call, with the captured path params in the order
+    /// they appear in the route template.
+    Found(Rc<RefCell<FinalRouteService>>, Vec<(String, String)>),
+    /// An `OPTIONS` request for a path with no handler of its own for it.
+    /// Holds the methods the path allows.
+    Options(Vec<Method>),
+    /// The path exists, but not for this method. Holds the methods it has.
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
 
 pub struct AllRouteServices {
     route: RadixRouter,
-    services: Vec<Rc<RefCell<FinalRouteService>>>
+    services: Vec<MethodServices>,
+    // Normalized route template to its index in `services`
+    paths: HashMap<String, usize>,
+}
+
+impl Default for AllRouteServices {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AllRouteServices {
@@ -14,23 +65,86 @@ impl AllRouteServices {
     pub fn new() -> Self {
         Self {
             route: RadixRouter::new(),
-            services: Vec::new()
+            services: Vec::new(),
+            paths: HashMap::new(),
         }
     }
 
-    pub fn add_route(&mut self, path: &str, service: Rc<RefCell<FinalRouteService>>) {
-        self.services.push(service);
-        let index = self.services.len() - 1;
-        self.route.add_route(path, index);
+    /// Registers `service` for requests to `path` with any of `methods`, or
+    /// with any method at all if `methods` is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` already has a service for one of `methods`.
+    pub fn add_route(&mut self, path: &str, methods: &[Method], service: Rc<RefCell<FinalRouteService>>) {
+        let template: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let template = template.join("/");
+        let index = match self.paths.get(&template) {
+            Some(&index) => index,
+            None => {
+                let params = path
+                    .split('/')
+                    .filter_map(|segment| segment.strip_prefix(':'))
+                    .map(String::from)
+                    .collect();
+                self.services.push(MethodServices { params, ..Default::default() });
+                let index = self.services.len() - 1;
+                self.route.add_route(path, index);
+                self.paths.insert(template, index);
+                index
+            }
+        };
+        let services = &mut self.services[index];
+        if methods.is_empty() {
+            if services.any.is_some() {
+                panic!("Route for any method on {} is registered twice", path);
+            }
+            services.any = Some(service);
+            return;
+        }
+        for method in methods {
+            if services.methods.iter().any(|(m, _)| m == method) {
+                panic!("Route {} {} is registered twice", method, path);
+            }
+            services.methods.push((method.clone(), Rc::clone(&service)));
+        }
     }
 
-    pub fn find_route(&self, path: &str) -> Option<Rc<RefCell<FinalRouteService>>> {
-        let res = self.route.find_route(path);
-        if let Some(res) = res {
-            let service = Rc::clone(&self.services[res.0]);
-            return Some(service);
+    /// Finds the service for a request to `path` with `method`.
+    ///
+    /// Unless the app registered its own, `HEAD` requests go to the path's
+    /// `GET` service, and `OPTIONS` requests are answered by the server.
+    pub fn find_route(&self, path: &str, method: &str) -> RouteMatch {
+        let Some((index, mut captured)) = self.route.find_route(path) else {
+            return RouteMatch::NotFound;
+        };
+        let services = &self.services[index];
+        let params = services.params
+            .iter()
+            .filter_map(|name| captured.remove_entry(name))
+            .collect();
+        if let Some(service) = services.get(method) {
+            return RouteMatch::Found(Rc::clone(service), params);
+        }
+        if method == "HEAD" && let Some(service) = services.get("GET") {
+            return RouteMatch::Found(Rc::clone(service), params);
+        }
+        match &services.any {
+            Some(service) => RouteMatch::Found(Rc::clone(service), params),
+            None if method == "OPTIONS" => RouteMatch::Options(services.allowed()),
+            None => RouteMatch::MethodNotAllowed(services.allowed()),
         }
-        None
+    }
+
+    /// Every method some path answers, for `OPTIONS *`.
+    pub fn all_methods(&self) -> Vec<Method> {
+        let mut all = Vec::new();
+        for method in self.services.iter().flat_map(|services| services.allowed()) {
+            if !all.contains(&method) {
+                all.push(method);
+            }
+        }
+        all
     }
 }
 
@@ -38,6 +152,12 @@ pub struct Router {
     pub services: Vec<Box<dyn AppServiceFactory>>,
 }
 
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Router {
     pub fn new() -> Self {
         Router { 
@@ -59,3 +179,89 @@ impl Router {
       self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::route::{self, RouteServices};
+    use super::*;
+
+    fn routes(routes: Vec<Route>) -> AllRouteServices {
+        let mut config = RouteServices::new();
+        for mut route in routes {
+            route.register(&mut config);
+        }
+        let mut all = AllRouteServices::new();
+        for service in config.into_services() {
+            let (path, methods) = {
+                let s = service.borrow();
+                (s.route_name.clone(), s.methods.clone())
+            };
+            all.add_route(&path, &methods, service);
+        }
+        all
+    }
+
+    #[test]
+    fn dispatches_by_method() {
+        let all = routes(vec![route::get("/"), route::get("/users/:id"), route::post("/users/:id")]);
+
+        assert!(matches!(all.find_route("/users/1", "POST"), RouteMatch::Found(s, _) if s.borrow().methods == [Method::POST]));
+        match all.find_route("/users/1", "DELETE") {
+            RouteMatch::MethodNotAllowed(allowed) => {
+                assert_eq!(allowed, vec![Method::GET, Method::POST, Method::HEAD, Method::OPTIONS])
+            }
+            _ => panic!("expected 405"),
+        }
+        assert!(matches!(all.find_route("/nope", "GET"), RouteMatch::NotFound));
+        assert!(matches!(all.find_route("/users/1/extra", "GET"), RouteMatch::NotFound));
+    }
+
+    #[test]
+    fn captures_params_in_template_order() {
+        let all = routes(vec![route::get("/a/:x"), route::get("/v1/org/:org/user/:id")]);
+
+        match all.find_route("/v1/org/acme/user/42", "GET") {
+            RouteMatch::Found(_, params) => assert_eq!(
+                params,
+                vec![("org".to_string(), "acme".to_string()), ("id".to_string(), "42".to_string())]
+            ),
+            _ => panic!("expected a match"),
+        }
+        assert!(matches!(all.find_route("/a/1", "GET"), RouteMatch::Found(_, params) if params[0].1 == "1"));
+    }
+
+    #[test]
+    fn multi_method_and_any_routes() {
+        let all = routes(vec![
+            Route::new("/items/:id").methods(&[Method::PUT, Method::PATCH]),
+            route::any("/items/:id"),
+            route::get("/files").method(Method::Custom("PROPFIND".to_string())),
+        ]);
+
+        assert!(matches!(all.find_route("/items/1", "PATCH"), RouteMatch::Found(s, _) if s.borrow().methods.len() == 2));
+        assert!(matches!(all.find_route("/items/1", "DELETE"), RouteMatch::Found(s, _) if s.borrow().methods.is_empty()));
+        assert!(matches!(all.find_route("/files", "PROPFIND"), RouteMatch::Found(..)));
+        assert!(matches!(all.find_route("/files", "GET"), RouteMatch::MethodNotAllowed(_)));
+    }
+
+    #[test]
+    fn automatic_head_and_options() {
+        let all = routes(vec![
+            route::get("/"),
+            route::post("/upload"),
+            route::options("/upload"),
+            route::head("/files"),
+            route::get("/files"),
+        ]);
+
+        assert!(matches!(all.find_route("/", "HEAD"), RouteMatch::Found(s, _) if s.borrow().methods == [Method::GET]));
+        assert!(matches!(all.find_route("/files", "HEAD"), RouteMatch::Found(s, _) if s.borrow().methods == [Method::HEAD]));
+        assert!(matches!(all.find_route("/upload", "HEAD"), RouteMatch::MethodNotAllowed(_)));
+        match all.find_route("/", "OPTIONS") {
+            RouteMatch::Options(allowed) => assert_eq!(allowed, vec![Method::GET, Method::HEAD, Method::OPTIONS]),
+            _ => panic!("expected automatic OPTIONS"),
+        }
+        assert!(matches!(all.find_route("/upload", "OPTIONS"), RouteMatch::Found(..)));
+        assert_eq!(all.all_methods().len(), 4);
+    }
+}
@@ -1,5 +1,6 @@
 use loony_service::{ServiceFactory};
 use crate::{
+    error::Error,
     route::Route, 
     route::RouteServices, 
     resource::{Resource, FinalRouteService, FinalFutureRouteService}, 
@@ -10,7 +11,7 @@ pub type BoxedResourceServiceFactory = Box<
     dyn ServiceFactory<
         Request = ServiceRequest, 
         Response = ServiceResponse, 
-        Error = (), 
+        Error = Error, 
         Service = FinalRouteService,
         Config=(),
         InitError=(),
@@ -1,94 +1,357 @@
-use crate::{router::AllRouteServices, connection::Connection, error::*, response::HttpResponse};
+use crate::{route::Method, router::{AllRouteServices, RouteMatch}, connection::{Connection, IoStream}, error::*, response::{HttpResponse, StatusCode}};
 use crate::{app_service::AppHttpService, extensions::Extensions, request::HttpRequest, resource::FinalRouteService, service::ServiceRequest};
+use crate::app::{HandlerPanic, PanicHook};
+use crate::handle::{ServerHandle, Shutdown};
+use crate::listener::{Accept, Listener};
 
-use std::net::TcpListener;
-use async_std::task::block_on;
-use socket2::{Socket, Domain, Type};
+use futures_util::FutureExt;
+use tokio::{net::TcpListener, sync::{mpsc, watch, Notify, OwnedSemaphorePermit, Semaphore}, task::LocalSet};
 use loony_service::{IntoServiceFactory, Service, ServiceFactory};
-use std::{cell::RefCell, marker::PhantomData, net::TcpStream, rc::Rc, time::Duration};
+use std::{any::Any, cell::{Cell, RefCell}, io, marker::PhantomData, net::{SocketAddr, ToSocketAddrs}, panic::{self, AssertUnwindSafe}, rc::Rc, sync::Arc, thread, time::Duration};
+
+/// How long to wait before accepting again after a failed accept.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(50);
+
+/// How long a connection over the limit may take to receive its 503. It
+/// is fixed and short, so a flood cannot hold refused sockets open.
+const REFUSE_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub struct Run {
     // routes: AHashMap<String, Rc<RefCell<FinalRouteService>>>,
     extensions: Rc<Extensions>,
     route: AllRouteServices,
-    listener: std::net::TcpListener,
+    config: ServerConfig,
+    connections: Arc<Semaphore>,
+    shutdown: watch::Receiver<Shutdown>,
+    /// Connections currently being served by this worker.
+    active: Cell<usize>,
+    drained: Notify,
+    /// Whether error responses are rendered as `application/problem+json`.
+    problem_json: bool,
+    panic_hook: Option<PanicHook>,
 }
 
 impl Run {
-    fn run(&self) {
+    /// Accepts connections and serves each one on its own task, until the
+    /// server is told to stop.
+    ///
+    /// Must be polled from within a `LocalSet`, since route services are
+    /// `Rc`-based and never leave the worker thread.
+    async fn run<L: Accept>(self: Rc<Self>, listener: L) {
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = self.stopping() => return,
+            };
+            let stream = match accepted {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+                    // Most likely out of file descriptors; retrying at once
+                    // would spin the worker until one is freed.
+                    tokio::select! {
+                        _ = tokio::time::sleep(ACCEPT_BACKOFF) => continue,
+                        _ = self.stopping() => return,
+                    }
+                }
+            };
+            let run = Rc::clone(&self);
+            // Once `max_connections` are open across all workers, further
+            // clients are turned away instead of piling up on this worker.
+            let permit = match Arc::clone(&self.connections).try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    tokio::task::spawn_local(async move { run.refuse(stream).await });
+                    continue;
+                }
+            };
+            let active = ActiveConnection::new(run, permit);
+            tokio::task::spawn_local(async move {
+                if let Err(e) = active.run.handle_connection(stream).await {
+                    eprintln!("Connection error: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Resolves once the server has been told to stop.
+    async fn stopping(&self) {
+        let mut shutdown = self.shutdown.clone();
+        // An error means the controller is gone, which is as good as a stop.
+        let _ = shutdown.wait_for(|state| *state != Shutdown::Running).await;
+    }
+
+    fn is_stopping(&self) -> bool {
+        *self.shutdown.borrow() != Shutdown::Running
+    }
+
+    /// Resolves once every connection on this worker has been closed.
+    async fn drained(&self) {
         loop {
-            let (stream, _) = self.listener.accept().unwrap();            
-            self.handle_connection(stream).unwrap();
+            let notified = self.drained.notified();
+            if self.active.get() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Answers a connection over the limit with a 503 and closes it.
+    async fn refuse<S: IoStream>(&self, stream: S) {
+        if let Ok(mut connection) = Connection::new(stream, &self.config) {
+            let response = self.render_error(HttpResponse::new().status(StatusCode::ServiceUnavailable), None, None);
+            let _ = tokio::time::timeout(REFUSE_TIMEOUT, async {
+                connection.write_response(response, true, false, false).await?;
+                connection.close().await.map_err(ServerError::from)
+            }).await;
         }
     }
 
-    /// Handles an individual TCP connection
-    fn handle_connection(
+    /// Handles an individual client connection
+    async fn handle_connection<S: IoStream>(
         &self, 
-        stream: TcpStream,
+        stream: S,
     ) -> Result<(), ServerError> {
-        let mut connection = Connection::new(stream)?;
-        let bytes_read = connection.read_http_response()?;
-        let request = self.request(&bytes_read)?;
-        let response = self.response(request)?;
-        connection.write_str(&response)?;
-        connection.close()?;
+        let mut connection = Connection::new(stream, &self.config)?;
+        loop {
+            // An idle connection is closed as soon as the server stops, while
+            // one that has started sending a request gets its response.
+            let ready = tokio::select! {
+                ready = connection.wait_for_request() => ready?,
+                _ = self.stopping() => false,
+            };
+            if !ready {
+                break;
+            }
+            let read = connection.read_http_response().await;
+            let request = match read.and_then(|bytes| bytes.map(|b| self.request(b)).transpose()) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(e) => {
+                    if let Some(response) = self.rejection(&e) {
+                        let _ = connection.write_response(response, true, false, false).await;
+                    }
+                    return Err(e);
+                }
+            };
+            let keep_alive = request.keep_alive() && !self.config.keep_alive.is_zero();
+            // HTTP/1.0 has no chunked encoding, so a streamed body there can
+            // only end by closing the connection.
+            let chunked = request.version == Some(1);
+            let head_request = request.method.as_deref() == Some("HEAD");
+            let method = request.method.clone().unwrap_or_default();
+            let path = request.uri.as_deref().unwrap_or_default().split('?').next().unwrap_or_default().to_string();
+            let response = self.response(request).await?;
+            let keep_alive = keep_alive && !self.is_stopping() && (chunked || !response.is_streaming());
+            match connection.write_response(response, chunked, keep_alive, head_request).await {
+                // The head is already out, so all that is left is to report
+                // the panic and drop the connection.
+                Err(ServerError::StreamPanicked { message }) => {
+                    let route = self.route_name(&path, &method);
+                    self.report_panic(&HandlerPanic { method, path, route, message });
+                    return Ok(());
+                }
+                res => res?,
+            }
+            if !keep_alive {
+                break;
+            }
+        }
+        connection.close().await?;
         Ok(())
     }
 
 
     /// Parses raw HTTP request data into a structured Request object
-    fn request(&self, buffer: &[u8]) -> Result<HttpRequest, ServerError> {
+    fn request(&self, buffer: Vec<u8>) -> Result<HttpRequest, ServerError> {
         let mut request = HttpRequest::new();
-        let _ = request.parse(buffer).unwrap();
-        Ok(request.into())
+        request.parse_bytes(buffer.into())?;
+        Ok(request)
     }
 
     /// Handles an HTTP request and generates an appropriate response
-    fn response(
+    async fn response(
         &self,
-        request: HttpRequest,
-    ) -> Result<String, ServerError> {
-        let path = request.uri.as_ref()
-            .ok_or(HandlerError::MissingUri)?;
-        if let Some(service) = self.route.find_route(&path) {
-            self.execute_service(service.clone(), request)
-        } else {
-            Ok(HttpResponse::bad_request().build())
+        mut request: HttpRequest,
+    ) -> Result<HttpResponse, ServerError> {
+        let Some(uri) = request.uri.as_ref() else {
+            return Ok(self.error_response(&HandlerError::MissingUri, ""));
+        };
+        let path = uri.split('?').next().unwrap_or_default();
+        let method = request.method.as_deref().unwrap_or_default();
+        // `OPTIONS *` asks about the server as a whole
+        if path == "*" && method == "OPTIONS" {
+            return Ok(HttpResponse::ok().header("Allow", allow(&self.route.all_methods())));
+        }
+        match self.route.find_route(path, method) {
+            RouteMatch::Found(service, params) => {
+                request.path_params = params;
+                self.execute_service(service, request).await
+            }
+            RouteMatch::Options(allowed) => Ok(HttpResponse::ok().header("Allow", allow(&allowed))),
+            RouteMatch::MethodNotAllowed(allowed) => {
+                let err = HandlerError::MethodNotAllowed { route: path.to_string() };
+                let response = self.error_response(&err, path).header("Allow", allow(&allowed));
+                Ok(response)
+            }
+            RouteMatch::NotFound => Ok(self.error_response(&HandlerError::route_not_found(path), path)),
         }
     }
 
      /// Executes the appropriate service for the request
-    fn execute_service(
+    async fn execute_service(
         &self,
-        service: Rc<RefCell<FinalRouteService>>,
+        mut service: Rc<RefCell<FinalRouteService>>,
         request: HttpRequest
-    ) -> Result<String, ServerError> {
+    ) -> Result<HttpResponse, ServerError> {
         let service_request = ServiceRequest {
             req: request,
             extensions: self.extensions.clone(),
         };
 
-        let mut service_clone = Rc::clone(&service);
-        let future = service_clone.call(service_request);
-        
-        match block_on(future) {
-            Ok(response) => {
+        // The borrow on the service only lasts for `call`; the returned
+        // future is awaited without holding it, so other connections on
+        // this worker can use the same route concurrently.
+        let uri = service_request.req.uri.as_deref().unwrap_or_default();
+        let instance = uri.split('?').next().unwrap_or_default().to_string();
+        let method = service_request.req.method.clone().unwrap_or_default();
+
+        // A panic in a handler, or in an extractor, is caught here so it
+        // only fails this request instead of unwinding through the worker.
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| service.call(service_request))) {
+            Ok(future) => AssertUnwindSafe(future).catch_unwind().await,
+            Err(payload) => Err(payload),
+        };
+
+        match result {
+            Ok(Ok(response)) => {
                 Ok(response.0)
             }
-            Err(_) => {
-                Ok(HttpResponse::internal_server_error().build())
+            Ok(Err(e)) => {
+                if e.status_code().is_server_error() {
+                    eprintln!("Request failed: {}", e);
+                }
+                Ok(self.error_response(e.as_response_error(), &instance))
+            }
+            Err(payload) => {
+                let panic = HandlerPanic {
+                    method,
+                    route: service.borrow().route_name.clone(),
+                    path: instance,
+                    message: panic_message(payload.as_ref()),
+                };
+                Ok(self.handler_panicked(panic))
+            }
+        }
+    }
+
+    /// Logs and reports a handler panic, and builds the 500 sent for it.
+    fn handler_panicked(&self, panic: HandlerPanic) -> HttpResponse {
+        self.report_panic(&panic);
+        let err = HandlerError::internal_error(io::Error::other(panic.message));
+        self.error_response(&err, &panic.path)
+    }
+
+    /// Logs a handler panic and passes it to the app's panic hook.
+    fn report_panic(&self, panic: &HandlerPanic) {
+        eprintln!(
+            "Handler panicked: {} {} (route {}): {}",
+            panic.method, panic.path, panic.route, panic.message
+        );
+        if let Some(hook) = &self.panic_hook {
+            // A panicking hook must not take the worker down either.
+            if panic::catch_unwind(AssertUnwindSafe(|| hook(panic))).is_err() {
+                eprintln!("Panic hook panicked");
+            }
+        }
+    }
+
+    /// The pattern of the route `method` and `path` are served by.
+    fn route_name(&self, path: &str, method: &str) -> String {
+        match self.route.find_route(path, method) {
+            RouteMatch::Found(service, _) => service.borrow().route_name.clone(),
+            _ => String::new(),
+        }
+    }
+
+    /// The response for a request to `instance` that failed with `err`.
+    fn error_response(&self, err: &dyn ResponseError, instance: &str) -> HttpResponse {
+        // Server errors keep their details out of the response.
+        let detail = (!err.status_code().is_server_error()).then(|| err.to_string());
+        self.render_error(err.error_response(), detail.as_deref(), Some(instance))
+    }
+
+    /// Turns an error response into a problem details one, if the app
+    /// asked for that.
+    fn render_error(&self, response: HttpResponse, detail: Option<&str>, instance: Option<&str>) -> HttpResponse {
+        if self.problem_json {
+            response.problem(detail, instance)
+        } else {
+            response
+        }
+    }
+
+    /// The response sent before closing a connection whose request could
+    /// not be read, if the failure is one the client should hear about.
+    fn rejection(&self, err: &ServerError) -> Option<HttpResponse> {
+        match err {
+            ServerError::ParseError { source } => {
+                let detail = source.to_string();
+                Some(self.render_error(source.error_response(), Some(&detail), None))
             }
+            ServerError::TimeoutError { .. } => {
+                Some(self.render_error(HttpResponse::new().status(StatusCode::RequestTimeout), None, None))
+            }
+            _ => None,
         }
     }
+}
+
+/// Counts a connection as open on its worker, and holds its slot under
+/// `max_connections`, until dropped; even if the connection task panics.
+struct ActiveConnection {
+    run: Rc<Run>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ActiveConnection {
+    fn new(run: Rc<Run>, permit: OwnedSemaphorePermit) -> Self {
+        run.active.set(run.active.get() + 1);
+        ActiveConnection { run, _permit: permit }
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.run.active.set(self.run.active.get() - 1);
+        self.run.drained.notify_waiters();
+    }
+}
+
+/// The message a panic was raised with, if it was a string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
 
+/// Formats methods for an `Allow` header.
+fn allow(methods: &[Method]) -> String {
+    methods.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ")
 }
+
 pub struct ServeHttpService<F, I, T> 
 where F: Fn() -> I + Send + Clone + 'static,
 I: IntoServiceFactory<T>,
 T: ServiceFactory 
 {
     app: F,
+    config: ServerConfig,
+    connections: Arc<Semaphore>,
     _p: PhantomData<T>
 }
 
@@ -97,36 +360,79 @@ where F: Fn() -> I + Send + Clone + 'static,
     I: IntoServiceFactory<T>,
     T: ServiceFactory<Request=(), Config = (), Service = AppHttpService>,
 {
-    pub fn new(app: F) -> Self {
-        ServeHttpService { app, _p: PhantomData }
+    pub fn new(app: F, config: ServerConfig, connections: Arc<Semaphore>) -> Self {
+        ServeHttpService { app, config, connections, _p: PhantomData }
     }
     
-    pub fn run(&mut self, listener: std::net::TcpListener) {
-        let (extensions, route) = self.new_service().unwrap();
-        Run {
+    /// Builds the app and serves it on every listener until `shutdown`
+    /// says to stop, then runs the app's shutdown hooks.
+    ///
+    /// A graceful stop waits up to the configured shutdown timeout for open
+    /// connections to finish. Must be polled from within a `LocalSet`.
+    pub(crate) async fn run(
+        &mut self,
+        listeners: Vec<Listener>,
+        mut shutdown: watch::Receiver<Shutdown>,
+    ) -> Result<(), ServerError> {
+        let AppHttpService { extensions, route, shutdown_hooks, problem_json, panic_hook } = self.new_service().await?;
+        let run = Rc::new(Run {
             route,
             extensions: Rc::new(extensions),
-            listener,
-        }.run();
+            problem_json,
+            panic_hook,
+            config: self.config.clone(),
+            connections: Arc::clone(&self.connections),
+            shutdown: shutdown.clone(),
+            active: Cell::new(0),
+            drained: Notify::new(),
+        });
+        for listener in listeners {
+            match listener {
+                Listener::Tcp(listener) => {
+                    listener.set_nonblocking(true)?;
+                    let listener = TcpListener::from_std(listener)?;
+                    tokio::task::spawn_local(Rc::clone(&run).run(listener));
+                }
+                #[cfg(unix)]
+                Listener::Uds(listener, _) => {
+                    listener.set_nonblocking(true)?;
+                    let listener = tokio::net::UnixListener::from_std(listener)?;
+                    tokio::task::spawn_local(Rc::clone(&run).run(listener));
+                }
+            }
+        }
+
+        let graceful = shutdown
+            .wait_for(|state| *state != Shutdown::Running)
+            .await
+            .map(|state| *state == Shutdown::Graceful)
+            .unwrap_or(false);
+        if graceful {
+            let drain = tokio::time::timeout(self.config.shutdown_timeout, run.drained());
+            tokio::select! {
+                drained = drain => if drained.is_err() {
+                    eprintln!("Shutdown timeout elapsed, dropping {} open connections", run.active.get());
+                },
+                _ = shutdown.wait_for(|state| *state == Shutdown::Immediate) => {}
+            }
+        }
+
+        for hook in shutdown_hooks {
+            hook();
+        }
+        Ok(())
     }
 
     // /// Starts the server and initializes all services
-    fn new_service(&mut self) ->  Result<(
-        // AHashMap<String, Rc<RefCell<FinalRouteService>>>, 
-        Extensions, 
-        AllRouteServices
-    ), ServerError>
-    {
+    async fn new_service(&mut self) -> Result<AppHttpService, ServerError> {
         let app = (self.app)();
         let app_factory = app.into_factory();
         let app_service = app_factory.new_service(());
         
-        let http_service: Result<AppHttpService, T::InitError> = block_on(app_service);
+        let http_service: Result<AppHttpService, T::InitError> = app_service.await;
         
         match http_service {
-            Ok(service) => {
-                Ok((service.extensions, service.route))
-            }
+            Ok(service) => Ok(service),
             Err(_) => {
                 Err(ServerError::service_init_error(String::from("Failed to initialize app services.")))
             }
@@ -143,7 +449,9 @@ T: ServiceFactory,
 {
     app: F,
     config: ServerConfig,
-    port: i32,
+    workers: usize,
+    listeners: Vec<Listener>,
+    handle_signals: bool,
     _p: PhantomData<T>
 }
 
@@ -156,58 +464,316 @@ where F: Fn() -> I + Send + Clone + 'static,
         Self { 
             app,
             config: ServerConfig::default(),
-            port: 2443,
+            workers: default_workers(),
+            listeners: Vec::new(),
+            handle_signals: true,
             _p: PhantomData,
         }
     }
 
     /// Configures the server with custom settings
+    ///
+    /// Call this before `bind`, since sockets are created with the backlog
+    /// configured at that point.
     pub fn with_config(mut self, config: ServerConfig) -> Self {
         self.config = config;
         self
     }
 
-    pub fn bind(mut self, port: i32) -> Self {
-        self.port = port;
+    /// Binds a listener to every address `addr` resolves to.
+    ///
+    /// Can be called repeatedly to listen on several addresses. IPv6
+    /// addresses are bound dual-stack, so `[::]` accepts IPv4 clients too.
+    /// If nothing is bound, the server listens on `127.0.0.1` at the
+    /// configured port.
+    pub fn bind<A: ToSocketAddrs>(mut self, addr: A) -> io::Result<Self> {
+        let mut bound = false;
+        for addr in addr.to_socket_addrs()? {
+            self.listeners.push(Listener::bind_tcp(addr, self.config.backlog)?);
+            bound = true;
+        }
+        if !bound {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "address did not resolve to anything"));
+        }
+        Ok(self)
+    }
+
+    /// Serves on an already bound listener, e.g. one bound to port 0 in tests.
+    pub fn listen(mut self, listener: std::net::TcpListener) -> io::Result<Self> {
+        listener.local_addr()?;
+        self.listeners.push(Listener::Tcp(listener));
+        Ok(self)
+    }
+
+    /// Listens on a Unix domain socket at `path`.
+    ///
+    /// A stale socket file left behind by a previous run is removed; binding
+    /// fails if another server is still listening on it.
+    #[cfg(unix)]
+    pub fn bind_uds<P: AsRef<std::path::Path>>(mut self, path: P) -> io::Result<Self> {
+        self.listeners.push(Listener::bind_uds(path.as_ref(), None, self.config.backlog)?);
+        Ok(self)
+    }
+
+    /// Like `bind_uds`, but also sets the permissions of the socket file,
+    /// e.g. `0o660` to let a reverse proxy in the same group connect.
+    #[cfg(unix)]
+    pub fn bind_uds_with_permissions<P: AsRef<std::path::Path>>(mut self, path: P, mode: u32) -> io::Result<Self> {
+        self.listeners.push(Listener::bind_uds(path.as_ref(), Some(mode), self.config.backlog)?);
+        Ok(self)
+    }
+
+    /// Returns the TCP addresses the server is bound to, with the actual
+    /// ports assigned by the OS.
+    pub fn addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|l| l.local_addr())
+            .collect()
+    }
+
+    /// Sets the number of worker threads.
+    ///
+    /// Each worker runs its own single-threaded runtime with its own `App`
+    /// built from the factory closure, and accepts connections from every
+    /// listener. Defaults to the number of available CPUs.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets how long an idle keep-alive connection is kept open while
+    /// waiting for the next request. A zero duration disables keep-alive.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.config.keep_alive = keep_alive;
+        self
+    }
+
+    /// Sets how long a graceful shutdown waits for open connections to
+    /// finish before dropping them.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
         self
     }
-    /// Runs the HTTP server and starts accepting connections
+
+    /// Stops the server from shutting down on `SIGINT` and `SIGTERM`.
     ///
-    /// This method blocks the current thread and runs the server indefinitely
+    /// By default the first signal starts a graceful shutdown and a second
+    /// one forces it.
+    pub fn disable_signals(mut self) -> Self {
+        self.handle_signals = false;
+        self
+    }
+
+    /// Starts the worker threads and begins accepting connections.
+    ///
+    /// Returns a `ServerHandle` to stop the server with. Awaiting the handle
+    /// runs until the server has stopped, either through the handle or on
+    /// `SIGINT`/`SIGTERM`.
     ///
     /// # Panics
     ///
-    /// Panics if the server fails to start or service initialization fails
-    pub async fn run(&mut self) {
-        let mut servers = Vec::new();
-        for _ in 0..1 {
+    /// Panics if the server fails to start, see `try_run`.
+    pub fn run(self) -> ServerHandle {
+        self.try_run().unwrap_or_else(|e| panic!("Failed to start server: {}", e))
+    }
+
+    /// Like `run`, but returns an error if the default listener cannot be
+    /// bound or the worker threads cannot be started.
+    ///
+    /// A worker whose app fails to initialize logs the failure and exits;
+    /// the server stops once every worker has.
+    pub fn try_run(mut self) -> ServerResult<ServerHandle> {
+        if self.listeners.is_empty() {
+            let addr = SocketAddr::from(([127, 0, 0, 1], self.config.port));
+            let listener = Listener::bind_tcp(addr, self.config.backlog)
+                .map_err(|e| ServerError::ListenerBuildError { source: Box::new(e) })?;
+            self.listeners.push(listener);
+        }
+
+        let mut workers = Vec::with_capacity(self.workers);
+        let connections = Arc::new(Semaphore::new(self.config.max_connections.max(1)));
+        let (shutdown_tx, shutdown) = watch::channel(Shutdown::Running);
+        for idx in 0..self.workers {
             let app = self.app.clone();
-            let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
-            socket.set_reuse_port(true).unwrap();
-            socket.bind(&format!("127.0.0.1:{}", self.port).parse::<std::net::SocketAddr>().unwrap().into()).unwrap();
-            socket.listen(128).unwrap();
-            let listener: TcpListener = socket.into();
-
-            let handle = tokio::spawn(async move {
-                let mut t =ServeHttpService::new(app);
-                t.run(listener);
-            });
-            servers.push(handle);
+            let config = self.config.clone();
+            let connections = Arc::clone(&connections);
+            let shutdown = shutdown.clone();
+            // Every worker accepts from its own handle to the same sockets.
+            let listeners = self.listeners
+                .iter()
+                .map(|l| l.try_clone())
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(|e| ServerError::ListenerBuildError { source: Box::new(e) })?;
+
+            let spawned = thread::Builder::new()
+                .name(format!("loony-worker-{}", idx))
+                .spawn(move || run_worker(app, config, connections, listeners, shutdown));
+            match spawned {
+                Ok(handle) => workers.push(handle),
+                Err(e) => {
+                    // Workers already started would otherwise serve forever.
+                    let _ = shutdown_tx.send(Shutdown::Immediate);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let socket_paths: Vec<_> = self.listeners
+            .iter()
+            .filter_map(|l| l.socket_path().map(|p| p.to_path_buf()))
+            .collect();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let (stopped_tx, stopped) = watch::channel(false);
+        let handle_signals = self.handle_signals;
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .inspect_err(|_| {
+                let _ = shutdown_tx.send(Shutdown::Immediate);
+            })?;
+        thread::Builder::new()
+            .name("loony-controller".to_string())
+            .spawn(move || {
+                rt.block_on(control(workers, shutdown_tx, commands_rx, handle_signals));
+                for path in socket_paths {
+                    let _ = std::fs::remove_file(path);
+                }
+                let _ = stopped_tx.send(true);
+            })?;
+
+        Ok(ServerHandle { commands, stopped })
+    }
+
+}
+
+/// Relays stop requests from handles and signals to the workers, and waits
+/// for the workers to exit.
+///
+/// A graceful stop can be escalated by a forced one, or by a second signal.
+async fn control(
+    workers: Vec<thread::JoinHandle<()>>,
+    shutdown: watch::Sender<Shutdown>,
+    mut commands: mpsc::UnboundedReceiver<bool>,
+    handle_signals: bool,
+) {
+    let joined = futures_util::future::join_all(
+        workers.into_iter().map(|worker| tokio::task::spawn_blocking(move || worker.join())),
+    );
+    tokio::pin!(joined);
+    // Handles may all be dropped while the server keeps running.
+    let mut handles_alive = true;
+
+    loop {
+        let state = *shutdown.borrow();
+        let next = tokio::select! {
+            results = &mut joined => {
+                for res in results {
+                    if let Ok(Err(_)) = res {
+                        eprintln!("Worker thread panicked");
+                    }
+                }
+                return;
+            }
+            command = commands.recv(), if handles_alive => match command {
+                Some(true) if state == Shutdown::Running => Shutdown::Graceful,
+                Some(true) => state,
+                Some(false) => Shutdown::Immediate,
+                None => {
+                    handles_alive = false;
+                    continue;
+                }
+            },
+            _ = shutdown_signal(), if handle_signals => match state {
+                Shutdown::Running => Shutdown::Graceful,
+                _ => Shutdown::Immediate,
+            },
+        };
+        let _ = shutdown.send(next);
+    }
+}
+
+/// Resolves on `SIGINT`, or `SIGTERM` on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            },
+            Err(e) => {
+                eprintln!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
         }
-            
-        // Run all servers
-        futures_util::future::join_all(servers).await;
     }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
 
+/// Runs a single worker to completion on the current thread.
+///
+/// Route services are `Rc`-based, so every worker owns a current-thread
+/// runtime and spawns its connection tasks onto a `LocalSet`.
+fn run_worker<F, I, T>(
+    app: F,
+    config: ServerConfig,
+    connections: Arc<Semaphore>,
+    listeners: Vec<Listener>,
+    shutdown: watch::Receiver<Shutdown>,
+)
+where F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<T>,
+    T: ServiceFactory<Request=(), Config = (), Service = AppHttpService>,
+{
+    let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to start worker runtime: {}", e);
+            return;
+        }
+    };
+    let local = LocalSet::new();
+    local.block_on(&rt, async move {
+        let mut t = ServeHttpService::new(app, config, connections);
+        if let Err(e) = t.run(listeners, shutdown).await {
+            eprintln!("Worker failed: {}", e);
+        }
+    });
+}
+
+fn default_workers() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
 }
 
 /// Server configuration
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
     pub port: u16,
+    /// Maximum number of open connections across all workers. Connections
+    /// beyond the limit are answered with `503 Service Unavailable`.
     pub max_connections: usize,
+    /// Maximum time to wait for the client while reading a request body.
     pub read_timeout: Duration,
+    /// Maximum time a single response write may take.
     pub write_timeout: Duration,
+    /// How long an idle connection waits for its next request.
+    pub keep_alive: Duration,
+    /// Maximum time for a client to send a complete request head.
+    pub header_read_timeout: Duration,
+    /// Maximum size in bytes of a request, headers and body included.
+    pub max_request_size: usize,
+    /// Maximum size in bytes of a request head. Larger ones are answered
+    /// with `431 Request Header Fields Too Large`.
+    pub max_head_size: usize,
+    /// Size of the pending connection queue of each listener.
+    pub backlog: i32,
+    /// How long a graceful shutdown waits for open connections to finish.
+    pub shutdown_timeout: Duration,
 }
 
 impl Default for ServerConfig {
@@ -217,6 +783,312 @@ impl Default for ServerConfig {
             max_connections: 1000,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            keep_alive: Duration::from_secs(5),
+            header_read_timeout: Duration::from_secs(5),
+            max_request_size: 4 * 1024 * 1024,
+            max_head_size: 64 * 1024,
+            backlog: 1024,
+            shutdown_timeout: Duration::from_secs(30),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Mutex};
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use crate::App;
+    use super::*;
+
+    /// Starts a one-worker server for `app` with `config`.
+    fn serve<F>(app: F, config: ServerConfig) -> (SocketAddr, ServerHandle)
+    where F: Fn() -> App + Send + Clone + 'static,
+    {
+        let server = HttpServer::new(app)
+            .with_config(config)
+            .workers(1)
+            .disable_signals()
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.addrs()[0];
+        (addr, server.run())
+    }
+
+    /// Writes `request` to `stream` and reads until the server closes it.
+    async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &[u8]) -> String {
+        stream.write_all(request).await.unwrap();
+        let mut response = Vec::new();
+        // A connection the server drops reads as cut off.
+        let _ = stream.read_to_end(&mut response).await;
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    /// Sends `request` over a new connection to `addr`.
+    async fn send(addr: SocketAddr, request: &[u8]) -> String {
+        exchange(TcpStream::connect(addr).await.unwrap(), request).await
+    }
+
+    /// A worker without routes, outside of a server.
+    fn test_run() -> (watch::Sender<Shutdown>, Rc<Run>) {
+        let (stop, shutdown) = watch::channel(Shutdown::Running);
+        let run = Rc::new(Run {
+            extensions: Rc::new(Extensions::new()),
+            route: AllRouteServices::new(),
+            config: ServerConfig::default(),
+            connections: Arc::new(Semaphore::new(1)),
+            shutdown,
+            active: Cell::new(0),
+            drained: Notify::new(),
+            problem_json: false,
+            panic_hook: None,
+        });
+        (stop, run)
+    }
+
+    #[test]
+    fn bind_reports_assigned_ports() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let prebuilt = listener.local_addr().unwrap();
+        let server = HttpServer::new(App::new)
+            .bind("127.0.0.1:0")
+            .unwrap()
+            .listen(listener)
+            .unwrap();
+
+        let addrs = server.addrs();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0].port(), 0);
+        assert_eq!(addrs[1], prebuilt);
+    }
+
+    #[tokio::test]
+    async fn stop_runs_shutdown_hooks() {
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let hooks = Arc::clone(&stopped);
+        let handle = HttpServer::new(move || {
+                let hooks = Arc::clone(&hooks);
+                App::new().on_shutdown(move || {
+                    hooks.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .workers(2)
+            .disable_signals()
+            .bind("127.0.0.1:0")
+            .unwrap()
+            .run();
+
+        handle.stop(true).await;
+        assert_eq!(stopped.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn renders_problem_json_errors() {
+        let (addr, handle) = serve(|| App::new().problem_json(), ServerConfig::default());
+        let response = send(addr, b"GET /missing?x=1 HTTP/1.1\r\nConnection: close\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+        assert!(response.contains("Content-Type: application/problem+json"), "{}", response);
+        let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["title"], "Not Found");
+        assert_eq!(body["instance"], "/missing");
+        handle.stop(true).await;
+    }
+
+    #[tokio::test]
+    async fn survives_handler_panics() {
+        async fn boom(_: ()) -> String {
+            panic!("boom")
+        }
+        async fn ok(_: ()) -> String {
+            "ok".to_string()
+        }
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let hook = Arc::clone(&reported);
+        let (addr, handle) = serve(move || {
+            let hook = Arc::clone(&hook);
+            App::new()
+                .route(crate::route::get("/boom/:id").to(boom))
+                .route(crate::route::get("/ok").to(ok))
+                .on_panic(move |panic| hook.lock().unwrap().push(panic.clone()))
+        }, ServerConfig::default());
+
+        for (path, status) in [("/boom/7?x=1", "500"), ("/ok", "200")] {
+            let request = format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path);
+            let response = send(addr, request.as_bytes()).await;
+            assert!(response.starts_with(&format!("HTTP/1.1 {}", status)), "{}", response);
+            assert!(!response.contains("boom"), "{}", response);
+        }
+
+        let reported = reported.lock().unwrap().clone();
+        assert_eq!(reported.len(), 1);
+        assert_eq!((reported[0].method.as_str(), reported[0].path.as_str()), ("GET", "/boom/7"));
+        assert_eq!((reported[0].route.as_str(), reported[0].message.as_str()), ("/boom/:id", "boom"));
+        handle.stop(true).await;
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_refused_clients_quickly() {
+        // A client that never reads leaves the 503 stuck in a full buffer.
+        let (_client, server) = tokio::io::duplex(16);
+        let (_stop, run) = test_run();
+        tokio::time::timeout(REFUSE_TIMEOUT * 2, run.refuse(server)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn backs_off_after_failed_accepts() {
+        struct Failing(Rc<Cell<usize>>);
+
+        impl Accept for Failing {
+            type Stream = TcpStream;
+
+            async fn accept(&self) -> io::Result<Self::Stream> {
+                self.0.set(self.0.get() + 1);
+                Err(io::Error::other("too many open files"))
+            }
+        }
+
+        let (_stop, run) = test_run();
+        let attempts = Rc::new(Cell::new(0));
+        let _ = tokio::time::timeout(Duration::from_millis(200), run.run(Failing(Rc::clone(&attempts)))).await;
+        assert!((2..=6).contains(&attempts.get()), "{} attempts", attempts.get());
+    }
+
+    #[tokio::test]
+    async fn refuses_connections_over_the_limit() {
+        let (addr, handle) = serve(App::new, ServerConfig { max_connections: 1, ..Default::default() });
+        let held = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = send(addr, b"").await;
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+
+        // Closing the first connection frees its slot.
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = send(addr, b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+        handle.stop(true).await;
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_and_idle_connections() {
+        let timeout = Duration::from_millis(200);
+        let (addr, handle) = serve(App::new, ServerConfig {
+            read_timeout: timeout,
+            header_read_timeout: timeout,
+            keep_alive: timeout,
+            ..Default::default()
+        });
+        let respond = |request| async move {
+            tokio::time::timeout(Duration::from_secs(2), send(addr, request))
+                .await
+                .expect("the server should close the connection")
+        };
+
+        // A client that never sends anything is dropped quietly.
+        assert_eq!(respond(b"").await, "");
+
+        // A request head or body that stops arriving is answered with 408.
+        for partial in [&b"GET / HTTP/1.1\r\nHost: x\r\n"[..], b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc"] {
+            let response = respond(partial).await;
+            assert!(response.starts_with("HTTP/1.1 408"), "{}", response);
+        }
+
+        // A kept-alive connection is closed once idle for the keep-alive
+        // timeout, after its response.
+        let response = respond(b"GET / HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+        assert!(response.contains("Connection: keep-alive"), "{}", response);
+        handle.stop(true).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_and_removes_unix_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("loony.sock");
+        let handle = HttpServer::new(App::new)
+            .workers(1)
+            .disable_signals()
+            .bind_uds(&path)
+            .unwrap()
+            .run();
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let response = exchange(stream, b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+        handle.stop(true).await;
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn graceful_stop_finishes_requests_and_immediate_stop_drops_them() {
+        static FINISHED: AtomicBool = AtomicBool::new(false);
+        async fn slow(_: ()) -> String {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            FINISHED.store(true, Ordering::SeqCst);
+            "done".to_string()
+        }
+        let app = || App::new().route(crate::route::get("/slow").to(slow));
+        let start = || async move {
+            let (addr, handle) = serve(app, ServerConfig::default());
+            let response = tokio::spawn(send(addr, b"GET /slow HTTP/1.1\r\n\r\n"));
+            // Let the handler start before stopping.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            (response, handle)
+        };
+
+        let (response, handle) = start().await;
+        handle.stop(true).await;
+        assert!(FINISHED.load(Ordering::SeqCst), "stop resolved before the request finished");
+        let response = response.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("Connection: close"), "{}", response);
+        assert!(response.ends_with("done"), "{}", response);
+
+        FINISHED.store(false, Ordering::SeqCst);
+        let (response, handle) = start().await;
+        handle.stop(false).await;
+        assert!(!FINISHED.load(Ordering::SeqCst), "an immediate stop waited for the request");
+        let response = response.await.unwrap();
+        assert!(response.is_empty(), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn reports_panicking_body_streams() {
+        async fn export(_: ()) -> crate::responder::Streaming<impl futures_util::Stream<Item = io::Result<bytes::Bytes>>> {
+            crate::responder::Streaming(futures_util::stream::iter(0..2).map(|i| {
+                if i == 1 {
+                    panic!("stream boom");
+                }
+                Ok(bytes::Bytes::from_static(b"part"))
+            }))
+        }
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let hook = Arc::clone(&reported);
+        let (addr, handle) = serve(move || {
+            let hook = Arc::clone(&hook);
+            App::new()
+                .route(crate::route::get("/export").to(export))
+                .on_panic(move |panic| hook.lock().unwrap().push(panic.clone()))
+        }, ServerConfig::default());
+
+        let response = send(addr, b"GET /export HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("4\r\npart\r\n"), "{}", response);
+
+        let reported = reported.lock().unwrap().clone();
+        assert_eq!(reported.len(), 1);
+        assert_eq!((reported[0].route.as_str(), reported[0].message.as_str()), ("/export", "stream boom"));
+
+        // The connection no longer counts as open, so stopping does not
+        // wait out the shutdown timeout.
+        tokio::time::timeout(Duration::from_secs(5), handle.stop(true)).await.unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use crate::{route::RouteServices, extensions::Extensions, request::HttpRequest};
+use crate::{route::RouteServices, extensions::Extensions, request::HttpRequest, response::HttpResponse};
 
 pub trait HttpServiceFactory {
     fn register(self,  config: &mut RouteServices);
@@ -21,11 +21,7 @@ pub struct ServiceRequest{
     pub extensions: Rc<Extensions>
 }
 
-// #[derive(Clone)]
-// pub struct ServiceResponse(pub HttpResponse);
-
-#[derive(Clone)]
-pub struct ServiceResponse(pub String);
+pub struct ServiceResponse(pub HttpResponse);
 
 pub(crate) struct ServiceFactoryWrapper<T> {
     factory: Option<T>,
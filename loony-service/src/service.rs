@@ -162,7 +162,7 @@ where
     type Future = S::Future;
 
     fn call(&mut self, request: Self::Request) -> S::Future {
-        (&mut (**self).borrow_mut()).call(request)
+        (**self).borrow_mut().call(request)
     }
 }
 
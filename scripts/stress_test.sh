@@ -1,3 +1,4 @@
 #!/bin/bash
 
-ab -n 100000 -c 10 http://localhost:2000/
\ No newline at end of file
+ab -n 100000 -c 10 http://localhost:2000/
+ab -k -n 100000 -c 10 http://localhost:2000/
\ No newline at end of file
@@ -1,29 +1,23 @@
 use crate::DB;
-use loony_server::extract::{Data, Path};
-use loony_server::response::HttpResponse;
-use serde_json::json;
+use loony_server::extract::{Data, Json, Path};
+use serde_json::{json, Value};
 
-pub async fn get_user(_app: Data<DB>, Path(user_id): Path<i32>) -> HttpResponse {
-    // HttpResponse{value: json!({ "id": 1, "name": "User" }).to_string()}
-    HttpResponse::new().json(json!({ "id": user_id, "type": "get_user" })).unwrap()
+pub async fn get_user(_app: Data<DB>, Path(user_id): Path<i32>) -> Json<Value> {
+    Json(json!({ "id": user_id, "type": "get_user" }))
 }
 
-pub async fn get_user_name(_app: Data<DB>, Path((user_id, name)): Path<(i32, String)>) -> HttpResponse {
-    // HttpResponse{value: json!({ "id": 1, "name": "User" }).to_string()}
-    HttpResponse::new().json(json!({ "id": user_id, "name": name, "type": "get_user_name" })).unwrap()
+pub async fn get_user_name(_app: Data<DB>, Path((user_id, name)): Path<(i32, String)>) -> Json<Value> {
+    Json(json!({ "id": user_id, "name": name, "type": "get_user_name" }))
 }
 
-pub async fn delete_user(_app: Data<DB>, Path(user_id): Path<i32>) -> HttpResponse {
-    // HttpResponse{value: json!({ "id": 1, "name": "User" }).to_string()}
-    HttpResponse::new().json(json!({ "id": 1, "name": "User", "type": "delete_user" })).unwrap()
+pub async fn delete_user(_app: Data<DB>, Path(_user_id): Path<i32>) -> Json<Value> {
+    Json(json!({ "id": 1, "name": "User", "type": "delete_user" }))
 }
 
-pub async fn update_user(_app: Data<DB>, Path(user_id): Path<i32>) -> HttpResponse {
-    // HttpResponse{value: json!({ "id": 1, "name": "User" }).to_string()}
-    HttpResponse::new().json(json!({ "id": 1, "name": "User", "type": "update_user" })).unwrap()
+pub async fn update_user(_app: Data<DB>, Path(user_id): Path<i32>, Json(user): Json<Value>) -> Json<Value> {
+    Json(json!({ "id": user_id, "user": user, "type": "update_user" }))
 }
 
-pub async fn users() -> HttpResponse {
-    // HttpResponse{value: json!([{ "id": 1, "name": "User" }]).to_string()}
-    HttpResponse::new().json(json!({ "type": "users" })).unwrap()
+pub async fn users() -> Json<Value> {
+    Json(json!({ "type": "users" }))
 }
@@ -2,7 +2,7 @@ mod controller;
 mod connection;
 
 use loony_server::{
-    App, HttpServer, responder::Responder, route, router::Router
+    App, HttpServer, responder::Responder, route::{self, Method, Route}, router::Router
 };
 use crate::{connection::pg_connection};
 use deadpool_postgres::Pool;
@@ -19,14 +19,18 @@ fn routes() -> Router {
         .route(route::get("/all").to(controller::users))
         .route(route::get("/get/:user_id").to(controller::get_user))
         .route(route::get("/get/:user_id/:user_name").to(controller::get_user_name))
-        .route(route::get("/delete/:user_id").to(controller::delete_user))
-        .route(route::get("/update/:user_id").to(controller::update_user))
+        .route(route::delete("/delete/:user_id").to(controller::delete_user))
+        .route(
+            Route::new("/update/:user_id")
+            .methods(&[Method::PUT, Method::PATCH])
+            .to(controller::update_user)
+        )
     )
 }
 
 #[derive(Debug, Clone)]
 pub struct AppState {
-    name: String,
+    pub name: String,
 }
 
 #[derive(Clone)]
@@ -51,7 +55,8 @@ async fn main() {
         .data(db.clone())
         .routes(routes)
     )
-    .bind(2000)
+    .bind(("127.0.0.1", 2000))
+    .unwrap()
     .run().await;
 
 }   
\ No newline at end of file